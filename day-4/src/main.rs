@@ -1,136 +1,711 @@
 // Solution to day 4 of the Advent of Code challenge
 
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
 
 /// Reads the provided file, converting . to 0 and @ to 1 within an array
 fn parse_file(name: &str) -> Grid {
     let file = File::open(name).unwrap();
-    let reader = BufReader::new(file);
+    parse(BufReader::new(file)).unwrap()
+}
 
+/// Reads grid rows from any buffered reader, see `parse_file`
+fn parse<R: BufRead>(reader: R) -> Result<Grid, GridError> {
     let mut grid = Vec::new();
 
-    for line in reader.lines() {
-        // This will map characters automatically to their byte representation
-        let line_data: Vec<u8> = Vec::from(line.unwrap())
-            .iter()
-            .map(|x| match x {
-                46 => 0,
-                64 => 1,
-                _ => panic!("Unrecognised value in vector."),
+    for (row, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| GridError::Unreadable {
+            row,
+            kind: e.kind(),
+        })?;
+        let line_data = line
+            .chars()
+            .enumerate()
+            .map(|(col, found)| match found {
+                '.' => Ok(0),
+                '@' => Ok(1),
+                _ => Err(GridError::UnrecognisedCell { row, col, found }),
             })
-            .collect();
+            .collect::<Result<Vec<u8>, GridError>>()?;
         grid.push(line_data);
     }
     Grid::new(grid)
 }
 
-/// Stores a square grid of coordinates, representing the location of paper
-/// rolls, where a 1 indicates the presence of a roll and 0 the lack thereof.
+/// Reasons a set of rows cannot form a grid
+#[derive(Debug, PartialEq)]
+enum GridError {
+    /// A row with a different length to the first row
+    RaggedRow {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A cell which is neither `.` nor `@`
+    UnrecognisedCell { row: usize, col: usize, found: char },
+    /// A row which could not be read
+    Unreadable { row: usize, kind: io::ErrorKind },
+}
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GridError::RaggedRow {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Row {} has {} cells but the grid is {} cells wide.",
+                row, found, expected
+            ),
+            GridError::UnrecognisedCell { row, col, found } => write!(
+                f,
+                "Unrecognised cell {:?} at row {}, column {}.",
+                found, row, col
+            ),
+            GridError::Unreadable { row, kind } => {
+                write!(f, "Row {} could not be read: {}.", row, kind)
+            }
+        }
+    }
+}
+
+/// A position within the grid, counted from the top left cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Pos {
+    row: usize,
+    col: usize,
+}
+impl Pos {
+    fn new(row: usize, col: usize) -> Self {
+        Pos { row, col }
+    }
+    /// Moves the position by a (row, column) offset, returning None if either
+    /// coordinate would become negative
+    pub fn offset(&self, d_row: isize, d_col: isize) -> Option<Pos> {
+        Some(Pos::new(
+            self.row.checked_add_signed(d_row)?,
+            self.col.checked_add_signed(d_col)?,
+        ))
+    }
+    /// Moves the position by a (row, column) offset, wrapping around the edges
+    /// of a grid with the given dimensions
+    pub fn offset_wrapping(&self, d_row: isize, d_col: isize, n_rows: usize, n_cols: usize) -> Pos {
+        Pos::new(
+            (self.row as isize + d_row).rem_euclid(n_rows as isize) as usize,
+            (self.col as isize + d_col).rem_euclid(n_cols as isize) as usize,
+        )
+    }
+}
+
+/// Stores a rectangular grid of coordinates, representing the location of
+/// paper rolls, where a 1 indicates the presence of a roll and 0 the lack
+/// thereof. A grid with no rows has a width of 0.
 #[derive(Clone)]
 struct Grid {
     grid: Vec<Vec<u8>>,
-    x_size: usize,
-    y_size: usize,
+    n_rows: usize,
+    n_cols: usize,
 }
 impl Grid {
-    pub fn new(grid: Vec<Vec<u8>>) -> Self {
-        let x_size = grid.len();
-        let y_size = grid[0].len();
-        Grid {
-            grid,
-            x_size,
-            y_size,
+    /// Creates a grid from a set of rows, which must all be the same length
+    pub fn new(grid: Vec<Vec<u8>>) -> Result<Self, GridError> {
+        let n_rows = grid.len();
+        let n_cols = grid.first().map_or(0, |row| row.len());
+        if let Some((row, found)) = grid
+            .iter()
+            .map(|r| r.len())
+            .enumerate()
+            .find(|(_, len)| *len != n_cols)
+        {
+            return Err(GridError::RaggedRow {
+                row,
+                expected: n_cols,
+                found,
+            });
         }
+        Ok(Grid {
+            grid,
+            n_rows,
+            n_cols,
+        })
+    }
+    /// Checks whether a position lies within the grid
+    pub fn contains(&self, pos: Pos) -> bool {
+        pos.row < self.n_rows && pos.col < self.n_cols
+    }
+    /// Iterates over every position of the grid, row by row
+    pub fn positions(&self) -> impl Iterator<Item = Pos> + use<> {
+        let n_cols = self.n_cols;
+        (0..self.n_rows).flat_map(move |row| (0..n_cols).map(move |col| Pos::new(row, col)))
     }
     /// Retrieve a value from the grid
-    pub fn get(&self, x_pos: usize, y_pos: usize) -> u8 {
-        self.grid[x_pos][y_pos]
+    pub fn get(&self, pos: Pos) -> u8 {
+        self.grid[pos.row][pos.col]
     }
     /// Set a value within the grid
-    pub fn set(&mut self, x_pos: usize, y_pos: usize, value: u8) {
-        self.grid[x_pos][y_pos] = value;
+    pub fn set(&mut self, pos: Pos, value: u8) {
+        self.grid[pos.row][pos.col] = value;
     }
     /// Checks if a provided position has a roll
-    pub fn has_roll(&self, x_pos: usize, y_pos: usize) -> bool {
-        self.get(x_pos, y_pos) == 1
+    pub fn has_roll(&self, pos: Pos) -> bool {
+        self.get(pos) == 1
     }
-    /// Checks if a roll is accessible, as defined by having <= n adjacent rolls
-    pub fn can_be_accessed(&self, x_pos: usize, y_pos: usize, n_max: u8) -> bool {
+    /// Checks if a roll is accessible, as defined by having no more than the
+    /// rule's maximum number of rolls within its neighbourhood. Each cell is
+    /// counted at most once, and never the roll itself, even when repeated
+    /// offsets or wrapping on a small grid reach the same cell again.
+    pub fn can_be_accessed(&self, pos: Pos, rule: &AccessRule) -> bool {
+        let (reach_rows, reach_cols) = rule.reach;
+        if rule.wrap && (self.n_rows <= 2 * reach_rows || self.n_cols <= 2 * reach_cols) {
+            // Wrapping offsets can land on the same cell, so collect them first
+            let neighbours: HashSet<Pos> = rule
+                .offsets
+                .iter()
+                .map(|(d_row, d_col)| pos.offset_wrapping(*d_row, *d_col, self.n_rows, self.n_cols))
+                .filter(|p| *p != pos)
+                .collect();
+            return neighbours.iter().filter(|p| self.has_roll(**p)).count() <= rule.max_neighbours;
+        }
         let mut count = 0;
-        for i in 0..3 {
-            if (i == 0 && x_pos == 0) || (i == 2 && x_pos == self.x_size - 1) {
-                continue;
-            }
-            for j in 0..3 {
-                if (i == 1 && j == 1)
-                    || (j == 0 && y_pos == 0)
-                    || (j == 2 && y_pos == self.y_size - 1)
-                {
-                    continue;
-                }
-
-                count += self.get(x_pos + i - 1, y_pos + j - 1);
-                if count > n_max {
+        for (d_row, d_col) in &rule.offsets {
+            let neighbour = if rule.wrap {
+                Some(pos.offset_wrapping(*d_row, *d_col, self.n_rows, self.n_cols))
+            } else {
+                pos.offset(*d_row, *d_col).filter(|p| self.contains(*p))
+            };
+            if let Some(neighbour) = neighbour {
+                count += self.get(neighbour) as usize;
+                if count > rule.max_neighbours {
                     return false;
                 }
             }
         }
         true
     }
-    /// Remove a number of rolls from the grid at the provided indices
-    pub fn remove_rolls(&mut self, indices: &Vec<(usize, usize)>) {
-        for (x, y) in indices {
-            self.set(*x, *y, 0);
+    /// Remove a number of rolls from the grid at the provided positions
+    pub fn remove_rolls(&mut self, positions: &[Pos]) {
+        for pos in positions {
+            self.set(*pos, 0);
+        }
+    }
+}
+
+/// Defines which cells around a roll are considered to be its neighbours
+#[derive(Clone, Debug, PartialEq)]
+enum Neighbourhood {
+    /// All cells within the square of the given radius, i.e. 3x3 for radius 1
+    Moore { radius: usize },
+    /// All cells within the given Manhattan distance, i.e. the 4 orthogonal
+    /// cells for radius 1
+    VonNeumann { radius: usize },
+    /// Arbitrary (row, column) offsets relative to the roll, where (0, 0) is
+    /// ignored
+    Custom(Vec<(isize, isize)>),
+}
+impl Neighbourhood {
+    /// Expands the neighbourhood into the list of offsets it covers
+    pub fn offsets(&self) -> Vec<(isize, isize)> {
+        let square = |r: isize| (-r..=r).flat_map(move |dr| (-r..=r).map(move |dc| (dr, dc)));
+        let offsets: Vec<(isize, isize)> = match self {
+            Neighbourhood::Moore { radius } => square(*radius as isize).collect(),
+            Neighbourhood::VonNeumann { radius } => square(*radius as isize)
+                .filter(|(dr, dc)| dr.unsigned_abs() + dc.unsigned_abs() <= *radius)
+                .collect(),
+            Neighbourhood::Custom(offsets) => offsets.clone(),
+        };
+        offsets.into_iter().filter(|&o| o != (0, 0)).collect()
+    }
+}
+impl FromStr for Neighbourhood {
+    type Err = String;
+
+    /// Parses a neighbourhood of the form moore, moore:2, von-neumann:1 or
+    /// custom:-1,0;1,0 where a custom stencil is a list of row,col offsets.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, param) = s.split_once(':').unwrap_or((s, "1"));
+        let parse_radius = |p: &str| p.parse().map_err(|_| format!("Invalid radius {}.", p));
+        match kind {
+            "moore" => Ok(Neighbourhood::Moore {
+                radius: parse_radius(param)?,
+            }),
+            "von-neumann" => Ok(Neighbourhood::VonNeumann {
+                radius: parse_radius(param)?,
+            }),
+            "custom" => param
+                .split(';')
+                .map(|o| {
+                    o.split_once(',')
+                        .and_then(|(dr, dc)| Some((dr.parse().ok()?, dc.parse().ok()?)))
+                        .ok_or(format!("Invalid offset {}.", o))
+                })
+                .collect::<Result<_, _>>()
+                .map(Neighbourhood::Custom),
+            _ => Err(format!("Unrecognised neighbourhood {}.", kind)),
+        }
+    }
+}
+
+/// The rule deciding whether a forklift can access a roll, where a roll is
+/// accessible if at most `max_neighbours` rolls lie within its neighbourhood.
+/// When `wrap` is set the grid is treated as a torus, otherwise cells beyond
+/// the edge count as empty. Offsets are kept without repeats or the roll
+/// itself, along with the furthest row and column offset reached.
+#[derive(Clone, Debug)]
+struct AccessRule {
+    offsets: Vec<(isize, isize)>,
+    reach: (usize, usize),
+    max_neighbours: usize,
+    wrap: bool,
+}
+impl AccessRule {
+    pub fn new(neighbourhood: &Neighbourhood, max_neighbours: usize, wrap: bool) -> Self {
+        let mut offsets = neighbourhood.offsets();
+        offsets.sort();
+        offsets.dedup();
+        offsets.retain(|o| *o != (0, 0));
+        let reach = offsets.iter().fold((0, 0), |(rows, cols), (d_row, d_col)| {
+            (
+                rows.max(d_row.unsigned_abs()),
+                cols.max(d_col.unsigned_abs()),
+            )
+        });
+        AccessRule {
+            offsets,
+            reach,
+            max_neighbours,
+            wrap,
         }
     }
 }
+impl Default for AccessRule {
+    /// The puzzle rule, fewer than 4 rolls in the surrounding 3x3 square
+    fn default() -> Self {
+        AccessRule::new(&Neighbourhood::Moore { radius: 1 }, 3, false)
+    }
+}
 
 /// Finds the number of accessible rolls in the grid, where it is accessible if
-/// the number of adjacent rolls is less then 4.
-fn count_accessible_rolls(grid: &Grid) -> u32 {
+/// the number of neighbouring rolls is within the limit set by the rule.
+fn count_accessible_rolls(grid: &Grid, rule: &AccessRule) -> u32 {
     let mut total_accessible = 0;
-    for i in 0..grid.x_size {
-        for j in 0..grid.y_size {
-            if grid.has_roll(i, j) && grid.can_be_accessed(i, j, 3) {
-                total_accessible += 1
-            }
+    for pos in grid.positions() {
+        if grid.has_roll(pos) && grid.can_be_accessed(pos, rule) {
+            total_accessible += 1
         }
     }
     total_accessible
 }
 
 /// Identifies accessible rolls and removes them iteratively until None remain
-fn count_removeable_rolls(mut grid: Grid) -> usize {
-    let mut total_removed = 0;
-    loop {
-        let mut accessible_coords = Vec::new();
-        for i in 0..grid.x_size {
-            for j in 0..grid.y_size {
-                if grid.has_roll(i, j) && grid.can_be_accessed(i, j, 3) {
-                    accessible_coords.push((i, j));
+fn count_removeable_rolls(grid: Grid, rule: &AccessRule) -> usize {
+    RemovalHistory::record(grid, rule).total_removed()
+}
+
+/// Records the round in which each roll was removed while the grid is peeled,
+/// with round 1 being the rolls which are accessible in the initial grid. Rolls
+/// which are never removed have no round.
+struct RemovalHistory {
+    initial: Grid,
+    rounds: Vec<Vec<Option<usize>>>,
+    removed: Vec<Vec<Pos>>,
+}
+impl RemovalHistory {
+    /// Repeatedly removes all accessible rolls from the grid, keeping track of
+    /// which rolls were removed within each round.
+    pub fn record(grid: Grid, rule: &AccessRule) -> Self {
+        let initial = grid.clone();
+        let mut grid = grid;
+        let mut rounds = vec![vec![None; grid.n_cols]; grid.n_rows];
+        let mut removed = Vec::new();
+        loop {
+            let accessible_coords: Vec<Pos> = grid
+                .positions()
+                .filter(|pos| grid.has_roll(*pos) && grid.can_be_accessed(*pos, rule))
+                .collect();
+            if accessible_coords.is_empty() {
+                break;
+            }
+            grid.remove_rolls(&accessible_coords);
+            for pos in &accessible_coords {
+                rounds[pos.row][pos.col] = Some(removed.len() + 1);
+            }
+            removed.push(accessible_coords);
+        }
+        RemovalHistory {
+            initial,
+            rounds,
+            removed,
+        }
+    }
+    /// The number of rounds in which at least one roll was removed
+    pub fn n_rounds(&self) -> usize {
+        self.removed.len()
+    }
+    /// The round in which the roll at a position was removed, if it ever was
+    pub fn round_of(&self, pos: Pos) -> Option<usize> {
+        self.rounds[pos.row][pos.col]
+    }
+    /// The positions of the rolls removed within a round, in scan order. No
+    /// rolls are removed in round 0, the initial grid.
+    pub fn removed_in_round(&self, round: usize) -> &[Pos] {
+        match round.checked_sub(1) {
+            Some(i) => &self.removed[i],
+            None => &[],
+        }
+    }
+    pub fn total_removed(&self) -> usize {
+        self.removed.iter().map(|r| r.len()).sum()
+    }
+    /// The state of a cell after a number of rounds have been completed
+    fn cell_after(&self, pos: Pos, round: usize) -> Cell {
+        if !self.initial.has_roll(pos) {
+            return Cell::Empty;
+        }
+        match self.round_of(pos) {
+            Some(r) if r < round => Cell::RemovedEarlier,
+            Some(r) if r == round => Cell::RemovedNow,
+            _ => Cell::Roll,
+        }
+    }
+    /// Renders the grid after a number of rounds as text, where @ is a roll,
+    /// x a roll removed in that round and . an empty space. Round 0 renders
+    /// the initial grid.
+    pub fn text_frame(&self, round: usize) -> String {
+        let mut frame = String::new();
+        for row in 0..self.initial.n_rows {
+            for col in 0..self.initial.n_cols {
+                frame.push(match self.cell_after(Pos::new(row, col), round) {
+                    Cell::Roll => '@',
+                    Cell::RemovedNow => 'x',
+                    Cell::RemovedEarlier | Cell::Empty => '.',
+                });
+            }
+            frame.push('\n');
+        }
+        frame
+    }
+    /// Writes the grid after a number of rounds as a binary PPM image, where
+    /// each cell is drawn as a square of scale x scale pixels.
+    pub fn write_ppm_frame<W: Write>(
+        &self,
+        out: &mut W,
+        round: usize,
+        scale: usize,
+    ) -> io::Result<()> {
+        let width = self.initial.n_cols * scale;
+        let height = self.initial.n_rows * scale;
+        write!(out, "P6\n{} {}\n255\n", width, height)?;
+        for row in 0..self.initial.n_rows {
+            let mut pixels = Vec::with_capacity(width * 3);
+            for col in 0..self.initial.n_cols {
+                let colour = self.cell_after(Pos::new(row, col), round).colour();
+                for _ in 0..scale {
+                    pixels.extend(colour);
+                }
+            }
+            for _ in 0..scale {
+                out.write_all(&pixels)?;
+            }
+        }
+        Ok(())
+    }
+    /// Writes one file per round (including the initial grid as round 0) into
+    /// the provided directory, either as text or PPM images.
+    pub fn write_frames(&self, dir: &Path, format: FrameFormat) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        for round in 0..=self.n_rounds() {
+            match format {
+                FrameFormat::Text => {
+                    let path = dir.join(format!("frame_{:04}.txt", round));
+                    fs::write(path, self.text_frame(round))?;
+                }
+                FrameFormat::Ppm { scale } => {
+                    let path = dir.join(format!("frame_{:04}.ppm", round));
+                    let mut out = BufWriter::new(File::create(path)?);
+                    self.write_ppm_frame(&mut out, round, scale)?;
+                    out.flush()?;
                 }
             }
         }
-        if accessible_coords.is_empty() {
-            break;
+        Ok(())
+    }
+}
+
+/// State of a cell within a rendered frame
+enum Cell {
+    Roll,
+    RemovedNow,
+    RemovedEarlier,
+    Empty,
+}
+impl Cell {
+    fn colour(&self) -> [u8; 3] {
+        match self {
+            Cell::Roll => [120, 72, 32],
+            Cell::RemovedNow => [220, 50, 47],
+            Cell::RemovedEarlier => [210, 210, 210],
+            Cell::Empty => [255, 255, 255],
         }
-        grid.remove_rolls(&accessible_coords);
-        total_removed += accessible_coords.len();
     }
-    total_removed
+}
+
+/// Output format used when writing the frames of a removal history
+#[derive(Clone, Copy)]
+enum FrameFormat {
+    Text,
+    Ppm { scale: usize },
+}
+
+/// Returned when a rule uses a neighbourhood or wrapping the bit-packed grid
+/// cannot count
+#[derive(Debug, PartialEq)]
+struct UnsupportedRule;
+impl fmt::Display for UnsupportedRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Only the 3x3 neighbourhood without wrapping is supported"
+        )
+    }
+}
+
+/// Stores the grid as one bit per cell, with each row packed into 64 bit words
+/// so that the neighbours of 64 cells can be counted at once. Only the puzzle's
+/// 3x3 neighbourhood without wrapping is supported, but with any threshold.
+/// A row of empty words stands in for the rows beyond the top and bottom.
+struct BitGrid {
+    words: Vec<u64>,
+    empty: Vec<u64>,
+    words_per_row: usize,
+    n_rows: usize,
+}
+impl BitGrid {
+    /// Packs an existing grid, where bit j % 64 of word j / 64 is column j
+    pub fn new(grid: &Grid) -> Self {
+        let words_per_row = grid.n_cols.div_ceil(64);
+        let mut words = vec![0; grid.n_rows * words_per_row];
+        for pos in grid.positions().filter(|p| grid.has_roll(*p)) {
+            words[pos.row * words_per_row + pos.col / 64] |= 1 << (pos.col % 64);
+        }
+        BitGrid {
+            words,
+            empty: vec![0; words_per_row],
+            words_per_row,
+            n_rows: grid.n_rows,
+        }
+    }
+    fn words_in_row(&self, row: usize) -> &[u64] {
+        &self.words[row * self.words_per_row..(row + 1) * self.words_per_row]
+    }
+    pub fn count_rolls(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+    /// Writes a mask of the accessible rolls within a row to the output slice.
+    /// The 8 neighbour masks are summed into 4 bit planes, so that the count
+    /// for each cell is held across the same bit of each plane, and are then
+    /// compared with the threshold plane by plane.
+    fn accessible_in_row(&self, row: usize, max_neighbours: u8, out: &mut [u64]) {
+        let above = if row > 0 {
+            self.words_in_row(row - 1)
+        } else {
+            &self.empty
+        };
+        let below = if row + 1 < self.n_rows {
+            self.words_in_row(row + 1)
+        } else {
+            &self.empty
+        };
+        let current = self.words_in_row(row);
+
+        for k in 0..self.words_per_row {
+            let mut planes = [0_u64; 4];
+            for (words, include_centre) in [(above, true), (current, false), (below, true)] {
+                let prev = if k > 0 { words[k - 1] } else { 0 };
+                let next = words.get(k + 1).copied().unwrap_or(0);
+                // Bit j of west is the cell at column j - 1, and of east j + 1
+                let west = (words[k] << 1) | (prev >> 63);
+                let east = (words[k] >> 1) | (next << 63);
+                add_to_planes(&mut planes, west);
+                add_to_planes(&mut planes, east);
+                if include_centre {
+                    add_to_planes(&mut planes, words[k]);
+                }
+            }
+            let mut greater = 0;
+            let mut equal = !0;
+            for (b, plane) in planes.iter().enumerate().rev() {
+                if (max_neighbours >> b) & 1 == 1 {
+                    equal &= plane;
+                } else {
+                    greater |= equal & plane;
+                    equal &= !plane;
+                }
+            }
+            out[k] = current[k] & !greater;
+        }
+    }
+    /// Finds the threshold to compare neighbour counts with, if the bit-packed
+    /// grid can follow the rule
+    fn threshold(rule: &AccessRule) -> Result<u8, UnsupportedRule> {
+        if rule.wrap || rule.offsets != AccessRule::default().offsets {
+            return Err(UnsupportedRule);
+        }
+        // There are only 8 neighbours, so larger thresholds all behave alike
+        Ok(rule.max_neighbours.min(8) as u8)
+    }
+    /// Counts the number of rolls accessible under the rule
+    pub fn count_accessible(&self, rule: &AccessRule) -> Result<usize, UnsupportedRule> {
+        let max_neighbours = Self::threshold(rule)?;
+        let mut mask = vec![0; self.words_per_row];
+        Ok((0..self.n_rows)
+            .map(|i| {
+                self.accessible_in_row(i, max_neighbours, &mut mask);
+                mask.iter().map(|w| w.count_ones() as usize).sum::<usize>()
+            })
+            .sum())
+    }
+    /// Removes accessible rolls round by round until none remain, returning
+    /// the total number removed
+    pub fn peel(&mut self, rule: &AccessRule) -> Result<usize, UnsupportedRule> {
+        let max_neighbours = Self::threshold(rule)?;
+        let mut removable = vec![0; self.words.len()];
+        let mut total_removed = 0;
+        loop {
+            for i in 0..self.n_rows {
+                let range = i * self.words_per_row..(i + 1) * self.words_per_row;
+                self.accessible_in_row(i, max_neighbours, &mut removable[range]);
+            }
+            let n_removed: usize = removable.iter().map(|w| w.count_ones() as usize).sum();
+            if n_removed == 0 {
+                break;
+            }
+            for (word, mask) in self.words.iter_mut().zip(&removable) {
+                *word &= !mask;
+            }
+            total_removed += n_removed;
+        }
+        Ok(total_removed)
+    }
+}
+
+/// Adds a single bit mask to a bit-sliced counter, rippling the carry through
+/// the planes from least to most significant
+fn add_to_planes(planes: &mut [u64; 4], mask: u64) {
+    let mut carry = mask;
+    for plane in planes.iter_mut() {
+        let next_carry = *plane & carry;
+        *plane ^= carry;
+        carry = next_carry;
+    }
+}
+
+/// Generates a grid of the given size where roughly `density` percent of
+/// cells hold a roll, using a xorshift generator so runs are repeatable
+fn random_grid(n_rows: usize, n_cols: usize, density: u64, seed: u64) -> Grid {
+    let mut state = seed.max(1);
+    let grid = (0..n_rows)
+        .map(|_| {
+            (0..n_cols)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    (state % 100 < density) as u8
+                })
+                .collect()
+        })
+        .collect();
+    Grid::new(grid).unwrap()
+}
+
+/// Times counting and peeling a large random grid with both the byte grid and
+/// the bit-packed grid
+fn benchmark(size: usize) {
+    let grid = random_grid(size, size, 65, 2025);
+    let rule = AccessRule::default();
+    println!("Grid of {} x {} cells", size, size);
+
+    let start = Instant::now();
+    let total = count_accessible_rolls(&grid, &rule);
+    println!("Grid:    accessible = {} in {:?}", total, start.elapsed());
+    let start = Instant::now();
+    let total = count_removeable_rolls(grid.clone(), &rule);
+    println!("Grid:    removed = {} in {:?}", total, start.elapsed());
+
+    let start = Instant::now();
+    let mut bit_grid = BitGrid::new(&grid);
+    println!("BitGrid: packed in {:?}", start.elapsed());
+    let start = Instant::now();
+    let total = bit_grid.count_accessible(&rule).unwrap();
+    println!("BitGrid: accessible = {} in {:?}", total, start.elapsed());
+    let start = Instant::now();
+    let total = bit_grid.peel(&rule).unwrap();
+    println!("BitGrid: removed = {} in {:?}", total, start.elapsed());
+    println!("BitGrid: {} rolls remain", bit_grid.count_rolls());
 }
 
 fn main() {
     let grid = parse_file("input.txt");
+    let args: Vec<String> = env::args().collect();
 
-    // Part 1
-    let total = count_accessible_rolls(&grid);
-    println!("Total accessible rolls = {}", total);
+    match args.get(1).map(String::as_str) {
+        None => {
+            let rule = AccessRule::default();
 
-    // Part 2
-    let total = count_removeable_rolls(grid.clone());
-    println!("Total removed rolls = {}", total);
+            // Part 1
+            let total = count_accessible_rolls(&grid, &rule);
+            println!("Total accessible rolls = {}", total);
+
+            // Part 2
+            let total = count_removeable_rolls(grid.clone(), &rule);
+            println!("Total removed rolls = {}", total);
+        }
+        // Solve both parts under a different access rule, e.g. `rule moore:2 11 wrap`
+        Some("rule") => {
+            let neighbourhood: Neighbourhood = args[2].parse().unwrap();
+            let max_neighbours = args[3].parse().unwrap();
+            let wrap = args.get(4).is_some_and(|s| s == "wrap");
+            let rule = AccessRule::new(&neighbourhood, max_neighbours, wrap);
+
+            let total = count_accessible_rolls(&grid, &rule);
+            println!("Total accessible rolls = {}", total);
+            let total = count_removeable_rolls(grid.clone(), &rule);
+            println!("Total removed rolls = {}", total);
+        }
+        // Write the grid after each round of removals, e.g. `frames out ppm 4`
+        Some("frames") => {
+            let dir = args.get(2).map(String::as_str).unwrap_or("frames");
+            let format = match args.get(3).map(String::as_str) {
+                None | Some("text") => FrameFormat::Text,
+                Some("ppm") => FrameFormat::Ppm {
+                    scale: args.get(4).map_or(4, |s| s.parse().unwrap()),
+                },
+                Some(other) => panic!("Unrecognised frame format {}.", other),
+            };
+            let history = RemovalHistory::record(grid, &AccessRule::default());
+            history.write_frames(Path::new(dir), format).unwrap();
+            for round in 1..=history.n_rounds() {
+                let n_removed = history.removed_in_round(round).len();
+                println!("Round {} removed {} rolls", round, n_removed);
+            }
+            println!(
+                "Wrote {} frames ({} rounds) to {}",
+                history.n_rounds() + 1,
+                history.n_rounds(),
+                dir
+            );
+        }
+        // Compare the grid representations, ideally with `--release`
+        Some("bench") => benchmark(args.get(2).map_or(2000, |s| s.parse().unwrap())),
+        Some(other) => panic!("Unrecognised mode {}.", other),
+    }
 }
 
 #[cfg(test)]
@@ -140,12 +715,245 @@ mod tests {
     #[test]
     fn part_1() {
         let grid = parse_file("input.txt");
-        assert_eq!(count_accessible_rolls(&grid), 1351)
+        assert_eq!(count_accessible_rolls(&grid, &AccessRule::default()), 1351)
     }
 
     #[test]
     fn part_2() {
         let grid = parse_file("input.txt");
-        assert_eq!(count_removeable_rolls(grid.clone()), 8345)
+        assert_eq!(
+            count_removeable_rolls(grid.clone(), &AccessRule::default()),
+            8345
+        )
+    }
+
+    #[test]
+    fn removal_history_follows_peeling_order() {
+        let grid = parse_file("input.txt");
+        let rule = AccessRule::default();
+        let history = RemovalHistory::record(grid.clone(), &rule);
+        assert_eq!(history.total_removed(), 8345);
+        assert!(history.removed_in_round(0).is_empty());
+
+        // Every roll removed in a round must have been accessible in the grid
+        // left behind by the previous round
+        let mut replay = grid;
+        for round in 1..=history.n_rounds() {
+            for pos in history.removed_in_round(round) {
+                assert!(replay.has_roll(*pos) && replay.can_be_accessed(*pos, &rule));
+                assert_eq!(history.round_of(*pos), Some(round));
+            }
+            replay.remove_rolls(history.removed_in_round(round));
+        }
+        assert_eq!(count_accessible_rolls(&replay, &rule), 0);
+    }
+
+    #[test]
+    fn text_frames() {
+        let grid = parse("@@@\n@@@\n.@.\n".as_bytes()).unwrap();
+        let history = RemovalHistory::record(grid, &AccessRule::default());
+        assert_eq!(history.n_rounds(), 2);
+        assert_eq!(history.text_frame(0), "@@@\n@@@\n.@.\n");
+        assert_eq!(history.text_frame(1), "x@x\n@@@\n.x.\n");
+        assert_eq!(history.text_frame(2), ".x.\nxxx\n...\n");
+    }
+
+    #[test]
+    fn neighbourhoods() {
+        let moore: Neighbourhood = "moore".parse().unwrap();
+        assert_eq!(moore.offsets().len(), 8);
+        assert_eq!(
+            "moore:2".parse::<Neighbourhood>().unwrap().offsets().len(),
+            24
+        );
+        assert_eq!(
+            "von-neumann:1"
+                .parse::<Neighbourhood>()
+                .unwrap()
+                .offsets()
+                .len(),
+            4
+        );
+        assert_eq!(
+            "von-neumann:2"
+                .parse::<Neighbourhood>()
+                .unwrap()
+                .offsets()
+                .len(),
+            12
+        );
+        assert_eq!(
+            "custom:-1,0;0,0;1,0"
+                .parse::<Neighbourhood>()
+                .unwrap()
+                .offsets(),
+            vec![(-1, 0), (1, 0)]
+        );
+        assert!("hex:1".parse::<Neighbourhood>().is_err());
+    }
+
+    #[test]
+    fn wrapping_access() {
+        // Every roll of a full 3x3 torus sees all 8 other rolls, while without
+        // wrapping the corners only see 3
+        let grid = parse("@@@\n@@@\n@@@\n".as_bytes()).unwrap();
+        let moore = Neighbourhood::Moore { radius: 1 };
+        assert_eq!(
+            count_accessible_rolls(&grid, &AccessRule::new(&moore, 3, false)),
+            4
+        );
+        assert_eq!(
+            count_accessible_rolls(&grid, &AccessRule::new(&moore, 7, true)),
+            0
+        );
+        assert_eq!(
+            count_accessible_rolls(&grid, &AccessRule::new(&moore, 8, true)),
+            9
+        );
+
+        let von_neumann = Neighbourhood::VonNeumann { radius: 1 };
+        assert_eq!(
+            count_accessible_rolls(&grid, &AccessRule::new(&von_neumann, 2, false)),
+            4
+        );
+        assert_eq!(
+            count_accessible_rolls(&grid, &AccessRule::new(&von_neumann, 3, true)),
+            0
+        );
+
+        // Tori smaller than the neighbourhood see each cell once, and never
+        // the roll itself
+        let grid = parse("@\n".as_bytes()).unwrap();
+        assert_eq!(
+            count_accessible_rolls(&grid, &AccessRule::new(&moore, 0, true)),
+            1
+        );
+        let grid = parse("@@\n@@\n".as_bytes()).unwrap();
+        assert_eq!(
+            count_accessible_rolls(&grid, &AccessRule::new(&moore, 3, true)),
+            4
+        );
+        assert_eq!(
+            count_accessible_rolls(&grid, &AccessRule::new(&moore, 2, true)),
+            0
+        );
+
+        // Repeated custom offsets only count their cell once
+        let repeated: Neighbourhood = "custom:0,1;0,1".parse().unwrap();
+        assert_eq!(
+            count_accessible_rolls(&grid, &AccessRule::new(&repeated, 1, false)),
+            4
+        );
+    }
+
+    #[test]
+    fn bit_grid_matches_grid() {
+        let grid = parse_file("input.txt");
+        let rule = AccessRule::default();
+        let mut bit_grid = BitGrid::new(&grid);
+        assert_eq!(bit_grid.count_accessible(&rule), Ok(1351));
+        assert_eq!(bit_grid.peel(&rule), Ok(8345));
+
+        // A width which is not a multiple of 64 to exercise the word edges
+        let grid = random_grid(90, 150, 55, 7);
+        let moore = Neighbourhood::Moore { radius: 1 };
+        for max_neighbours in 0..=9 {
+            let rule = AccessRule::new(&moore, max_neighbours, false);
+            let mut bit_grid = BitGrid::new(&grid);
+            assert_eq!(
+                bit_grid.count_accessible(&rule),
+                Ok(count_accessible_rolls(&grid, &rule) as usize)
+            );
+            assert_eq!(
+                bit_grid.peel(&rule),
+                Ok(count_removeable_rolls(grid.clone(), &rule))
+            );
+        }
+
+        // Other neighbourhoods and wrapping are rejected
+        let bit_grid = BitGrid::new(&grid);
+        let wrap = AccessRule::new(&moore, 3, true);
+        assert_eq!(bit_grid.count_accessible(&wrap), Err(UnsupportedRule));
+        let larger = AccessRule::new(&Neighbourhood::Moore { radius: 2 }, 3, false);
+        assert_eq!(bit_grid.count_accessible(&larger), Err(UnsupportedRule));
+    }
+
+    #[test]
+    fn ragged_grid() {
+        let error = parse("@@@\n@@\n@@@\n".as_bytes()).err();
+        assert_eq!(
+            error,
+            Some(GridError::RaggedRow {
+                row: 1,
+                expected: 3,
+                found: 2
+            })
+        );
+        assert!(Grid::new(vec![vec![1], vec![1, 0]]).is_err());
+    }
+
+    #[test]
+    fn malformed_grid() {
+        let error = parse("@.@\n.#.\n".as_bytes()).err();
+        assert_eq!(
+            error,
+            Some(GridError::UnrecognisedCell {
+                row: 1,
+                col: 1,
+                found: '#'
+            })
+        );
+        let error = parse(&[b'@', 0xff, b'\n'][..]).err();
+        assert_eq!(
+            error,
+            Some(GridError::Unreadable {
+                row: 0,
+                kind: io::ErrorKind::InvalidData
+            })
+        );
+    }
+
+    #[test]
+    fn empty_grid() {
+        let rule = AccessRule::default();
+        for grid in [
+            parse("".as_bytes()).unwrap(),
+            Grid::new(vec![vec![]; 3]).unwrap(),
+        ] {
+            assert_eq!(count_accessible_rolls(&grid, &rule), 0);
+            assert_eq!(count_removeable_rolls(grid.clone(), &rule), 0);
+            assert_eq!(BitGrid::new(&grid).peel(&rule), Ok(0));
+        }
+    }
+
+    #[test]
+    fn single_row_and_column() {
+        // Each roll has at most 2 neighbours, so everything goes in one round
+        let rule = AccessRule::default();
+        for text in ["@@.@@@@", "@\n@\n.\n@\n@\n@\n@\n"] {
+            let grid = parse(text.as_bytes()).unwrap();
+            assert_eq!(count_accessible_rolls(&grid, &rule), 6);
+            let history = RemovalHistory::record(grid.clone(), &rule);
+            assert_eq!((history.n_rounds(), history.total_removed()), (1, 6));
+            assert_eq!(BitGrid::new(&grid).peel(&rule), Ok(6));
+        }
+
+        let wrap = AccessRule::new(&Neighbourhood::Moore { radius: 1 }, 1, true);
+        let grid = parse("@@@".as_bytes()).unwrap();
+        assert_eq!(count_accessible_rolls(&grid, &wrap), 0);
+    }
+
+    #[test]
+    fn pos_offsets() {
+        let pos = Pos::new(0, 2);
+        assert_eq!(pos.offset(1, -2), Some(Pos::new(1, 0)));
+        assert_eq!(pos.offset(-1, 0), None);
+        assert_eq!(pos.offset_wrapping(-1, 1, 4, 3), Pos::new(3, 0));
+
+        // Rows and columns are not interchangeable on a non-square grid
+        let grid = parse("@..\n...\n".as_bytes()).unwrap();
+        assert!(grid.has_roll(Pos::new(0, 0)));
+        assert!(grid.contains(Pos::new(1, 2)) && !grid.contains(Pos::new(2, 1)));
+        assert_eq!(grid.positions().last(), Some(Pos::new(1, 2)));
     }
 }
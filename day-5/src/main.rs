@@ -1,9 +1,11 @@
 // Solution to day 5 of the Advent of Code challenge
 
 use std::cmp::max;
+use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::ops::RangeInclusive;
+use std::time::Instant;
 
 /// Reads the specified value and generates the required id ranges and ids.
 fn parse_file(name: &str) -> (Vec<RangeInclusive<u64>>, Vec<u64>) {
@@ -19,10 +21,7 @@ fn parse_file(name: &str) -> (Vec<RangeInclusive<u64>>, Vec<u64>) {
             is_range = false;
             continue;
         } else if is_range {
-            let range = line_data.split_once("-").unwrap();
-            let start = range.0.parse().unwrap();
-            let end = range.1.parse().unwrap();
-            ranges.push(start..=end);
+            ranges.push(parse_range(&line_data));
         } else {
             ids.push(line_data.parse::<u64>().unwrap());
         }
@@ -31,59 +30,433 @@ fn parse_file(name: &str) -> (Vec<RangeInclusive<u64>>, Vec<u64>) {
     (ranges, ids)
 }
 
+/// Parses a single range of the form start-end
+fn parse_range(line: &str) -> RangeInclusive<u64> {
+    let range = line.split_once("-").unwrap();
+    let start = range.0.parse().unwrap();
+    let end = range.1.parse().unwrap();
+    start..=end
+}
+
+/// Running totals of the ids checked so far
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Tally {
+    fresh: u64,
+    spoiled: u64,
+}
+
+/// Reads the range section of an inventory, then checks the ids one line at a
+/// time straight from the reader, so memory use does not grow with the number
+/// of ids. The progress callback is given the tally after every `report_every`
+/// ids, or never if `report_every` is 0.
+fn stream_inventory<R: BufRead, F: FnMut(&Tally)>(
+    mut reader: R,
+    report_every: u64,
+    mut on_progress: F,
+) -> Tally {
+    let mut line = String::new();
+    let mut ranges = Vec::new();
+    while reader.read_line(&mut line).unwrap() > 0 {
+        let line_data = line.trim_end();
+        if line_data.is_empty() {
+            break;
+        }
+        ranges.push(parse_range(line_data));
+        line.clear();
+    }
+    let fresh_ids = RangeSet::new(&ranges);
+
+    let mut tally = Tally::default();
+    line.clear();
+    while reader.read_line(&mut line).unwrap() > 0 {
+        let line_data = line.trim_end();
+        if !line_data.is_empty() {
+            if fresh_ids.contains(line_data.parse().unwrap()) {
+                tally.fresh += 1;
+            } else {
+                tally.spoiled += 1;
+            }
+            if report_every > 0 && (tally.fresh + tally.spoiled) % report_every == 0 {
+                on_progress(&tally);
+            }
+        }
+        line.clear();
+    }
+    tally
+}
+
 /// Sums the number of ids from the provided vector which exist with the set of
 /// valud ranges.
 fn count_id_in_range(ranges: &[RangeInclusive<u64>], ids: &[u64]) -> u64 {
-    ids.iter()
-        .map(|x| match ranges.iter().any(|r| r.contains(x)) {
-            true => 1_u64,
-            false => 0,
-        })
-        .sum()
+    RangeSet::new(ranges).count_contained(ids)
+}
+
+/// Stores a set of ids as sorted, non-overlapping ranges so that membership can
+/// be found with a binary search rather than checking every range.
+struct RangeSet {
+    ranges: Vec<RangeInclusive<u64>>,
+}
+impl RangeSet {
+    /// Builds the set from any ranges, merging overlapping/adjacent ranges
+    pub fn new(ranges: &[RangeInclusive<u64>]) -> Self {
+        RangeSet {
+            ranges: merge_ranges(ranges),
+        }
+    }
+    /// Checks whether an id is within the set by finding the first range which
+    /// does not end before the id.
+    pub fn contains(&self, id: u64) -> bool {
+        let i = self.ranges.partition_point(|r| *r.end() < id);
+        self.ranges.get(i).is_some_and(|r| r.contains(&id))
+    }
+    /// Counts how many of the ids are within the set. The ids are sorted so the
+    /// ranges can be swept once alongside them, which is faster than a binary
+    /// search per id for large batches.
+    pub fn count_contained(&self, ids: &[u64]) -> u64 {
+        let mut sorted_ids = ids.to_vec();
+        sorted_ids.sort_unstable();
+
+        let mut ranges = self.ranges.iter().peekable();
+        let mut total = 0;
+        for id in sorted_ids {
+            while ranges.next_if(|r| *r.end() < id).is_some() {}
+            match ranges.peek() {
+                Some(r) if r.contains(&id) => total += 1,
+                Some(_) => {}
+                None => break,
+            }
+        }
+        total
+    }
+    /// Returns the ids which are not within the set, in their original order
+    pub fn spoiled(&self, ids: &[u64]) -> Vec<u64> {
+        ids.iter().copied().filter(|x| !self.contains(*x)).collect()
+    }
+    /// Returns the runs of ids which lie between consecutive ranges of the set
+    pub fn gaps(&self) -> Vec<Gap> {
+        self.ranges
+            .windows(2)
+            .map(|w| Gap::new(w[0].end() + 1..=w[1].start() - 1))
+            .collect()
+    }
+    /// Returns the widest gap between ranges, or the first of these if several
+    /// are equally wide
+    pub fn largest_gap(&self) -> Option<Gap> {
+        self.gaps()
+            .into_iter()
+            .reduce(|best, gap| if gap.width > best.width { gap } else { best })
+    }
+}
+
+/// A run of spoiled ids between two fresh ranges
+#[derive(Clone, Debug, PartialEq)]
+struct Gap {
+    ids: RangeInclusive<u64>,
+    width: u64,
+}
+impl Gap {
+    fn new(ids: RangeInclusive<u64>) -> Self {
+        let width = ids.end() - ids.start() + 1;
+        Gap { ids, width }
+    }
 }
 
 /// Takes a vector of ranges and simplifies then by merging overlapping/adjacent
-/// ranges
+/// ranges. Empty ranges are dropped, so no ranges gives an empty vector.
 fn merge_ranges(ranges: &[RangeInclusive<u64>]) -> Vec<RangeInclusive<u64>> {
-    let mut sorted_ranges = ranges.to_vec();
+    let mut sorted_ranges: Vec<RangeInclusive<u64>> =
+        ranges.iter().filter(|r| !r.is_empty()).cloned().collect();
     sorted_ranges.sort_by(|a, b| a.start().cmp(b.start()));
 
-    let mut merged_ranges = vec![sorted_ranges.remove(0)];
+    let mut merged_ranges: Vec<RangeInclusive<u64>> = Vec::new();
     for range in sorted_ranges {
-        let previous_range = merged_ranges.last().unwrap();
-        if *range.start() <= previous_range.end() + 1 {
-            let new_range = *previous_range.start()..=*max(previous_range.end(), range.end());
-            let end_loc = merged_ranges.len() - 1;
-            merged_ranges[end_loc] = new_range;
-        } else {
-            merged_ranges.push(range);
+        match merged_ranges.last_mut() {
+            // Compare against start - 1 so that an end of u64::MAX cannot overflow
+            Some(previous_range) if range.start().saturating_sub(1) <= *previous_range.end() => {
+                *previous_range = *previous_range.start()..=*max(previous_range.end(), range.end());
+            }
+            _ => merged_ranges.push(range),
         }
     }
     merged_ranges
 }
 
-/// Sum the number of valid ids within the provided vector of ranges
-fn sum_ids_in_range(ranges: &[RangeInclusive<u64>]) -> u64 {
-    ranges.iter().map(|x| x.end() - x.start() + 1).sum()
+/// Sum the number of valid ids within the provided vector of ranges, which
+/// should not overlap. A u128 is used as the ranges may cover all 2^64 ids.
+fn sum_ids_in_range(ranges: &[RangeInclusive<u64>]) -> u128 {
+    ranges
+        .iter()
+        .filter(|x| !x.is_empty())
+        .map(|x| (x.end() - x.start()) as u128 + 1)
+        .sum()
+}
+
+/// A static interval tree over the original, unmerged ranges. Each range keeps
+/// its index within the input so it can be reported as the reason an id is
+/// fresh. The ranges are sorted by start and treated as an implicit balanced
+/// tree, where the middle of each slice is the node and each node also stores
+/// the largest end within its subtree.
+struct IntervalTree {
+    nodes: Vec<(RangeInclusive<u64>, usize)>,
+    max_end: Vec<u64>,
+}
+impl IntervalTree {
+    /// Builds the tree, skipping any empty ranges as they cannot contain an id
+    pub fn new(ranges: &[RangeInclusive<u64>]) -> Self {
+        let mut nodes: Vec<(RangeInclusive<u64>, usize)> = ranges
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(|(_, r)| !r.is_empty())
+            .map(|(i, r)| (r, i))
+            .collect();
+        nodes.sort_by_key(|(r, i)| (*r.start(), *i));
+
+        let mut tree = IntervalTree {
+            max_end: vec![0; nodes.len()],
+            nodes,
+        };
+        tree.fill_max_end(0, tree.nodes.len());
+        tree
+    }
+    /// Computes the largest end within the subtree covering nodes[lo..hi],
+    /// storing it against the subtree's root
+    fn fill_max_end(&mut self, lo: usize, hi: usize) -> u64 {
+        if lo >= hi {
+            return 0;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let left = self.fill_max_end(lo, mid);
+        let right = self.fill_max_end(mid + 1, hi);
+        self.max_end[mid] = max(*self.nodes[mid].0.end(), max(left, right));
+        self.max_end[mid]
+    }
+    /// Calls the visitor with the index of each range within nodes[lo..hi]
+    /// which contains the id
+    fn visit<F: FnMut(usize)>(&self, lo: usize, hi: usize, id: u64, visitor: &mut F) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.max_end[mid] < id {
+            return;
+        }
+        self.visit(lo, mid, id, visitor);
+        let (range, index) = &self.nodes[mid];
+        // Everything to the right starts at or after this range
+        if *range.start() <= id {
+            if id <= *range.end() {
+                visitor(*index);
+            }
+            self.visit(mid + 1, hi, id, visitor);
+        }
+    }
+    /// Returns the input indices of every range which contains the id, in
+    /// ascending order
+    pub fn containing(&self, id: u64) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.visit(0, self.nodes.len(), id, &mut |i| found.push(i));
+        found.sort_unstable();
+        found
+    }
+    /// The number of ranges which contain the id
+    pub fn count_overlapping(&self, id: u64) -> usize {
+        let mut count = 0;
+        self.visit(0, self.nodes.len(), id, &mut |_| count += 1);
+        count
+    }
+    /// Finds the largest number of ranges which overlap at any single id, and
+    /// the first run of ids at which that many overlap. Returns None if there
+    /// are no (non-empty) ranges.
+    pub fn max_depth(&self) -> Option<(usize, RangeInclusive<u64>)> {
+        // Each range adds one at its start and removes one after its end, with
+        // removals sorted first so that touching ranges do not overlap
+        let mut events: Vec<(u128, bool)> = self
+            .nodes
+            .iter()
+            .flat_map(|(r, _)| [(*r.start() as u128, true), (*r.end() as u128 + 1, false)])
+            .collect();
+        events.sort_unstable();
+
+        let mut depth = 0;
+        let mut best: Option<(usize, u128)> = None;
+        let mut best_end = 0;
+        for (i, (position, is_start)) in events.iter().enumerate() {
+            if *is_start {
+                depth += 1;
+            } else {
+                depth -= 1;
+            }
+            let next = events.get(i + 1).map(|e| e.0);
+            // Only positions where the depth holds for at least one id count
+            if next.is_some_and(|n| n > *position) && best.is_none_or(|(d, _)| depth > d) {
+                best = Some((depth, *position));
+                best_end = next.unwrap() - 1;
+            }
+        }
+        best.map(|(depth, start)| (depth, start as u64..=best_end as u64))
+    }
+}
+
+/// Generates a number of random ranges and ids using a xorshift generator, with
+/// range widths chosen so that a sizeable share of the ids are fresh
+fn random_inventory(n: usize, seed: u64) -> (Vec<RangeInclusive<u64>>, Vec<u64>) {
+    let mut state = seed.max(1);
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let span = 1 << 48;
+    let width = span / n as u64;
+    let ranges = (0..n)
+        .map(|_| {
+            let start = next() % span;
+            start..=start + next() % width
+        })
+        .collect();
+    let ids = (0..n).map(|_| next() % span).collect();
+    (ranges, ids)
+}
+
+/// Times the different ways of checking ids against the ranges
+fn benchmark(n: usize) {
+    let (ranges, ids) = random_inventory(n, 2025);
+    println!("{} ranges and {} ids", n, n);
+
+    let start = Instant::now();
+    let set = RangeSet::new(&ranges);
+    println!(
+        "Merged into {} ranges in {:?}",
+        set.ranges.len(),
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    let total = ids.iter().filter(|x| set.contains(**x)).count();
+    println!("Binary search: {} fresh in {:?}", total, start.elapsed());
+
+    let start = Instant::now();
+    let total = set.count_contained(&ids);
+    println!("Sorted sweep:  {} fresh in {:?}", total, start.elapsed());
+
+    // A linear scan of every id would take far too long, so time a sample
+    let sample = &ids[..ids.len().min(1000)];
+    let start = Instant::now();
+    let total = sample
+        .iter()
+        .filter(|x| set.ranges.iter().any(|r| r.contains(x)))
+        .count();
+    let per_id = start.elapsed() / sample.len() as u32;
+    println!(
+        "Linear scan:   {} fresh in the first {} ids, {:?} per id (~{:?} for all)",
+        total,
+        sample.len(),
+        per_id,
+        per_id * ids.len() as u32
+    );
 }
 
 fn main() {
-    let (ranges, ids) = parse_file("input.txt");
-    // Remove overlapping ranges now as this also benefits part 1
-    let simplified_ranges = merge_ranges(&ranges);
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        None => {
+            let (ranges, ids) = parse_file("input.txt");
+            // Remove overlapping ranges now as this also benefits part 1
+            let simplified_ranges = merge_ranges(&ranges);
 
-    // Part 1
-    let total = count_id_in_range(&simplified_ranges, &ids);
-    println!("Fresh ingredients = {}", total);
+            // Part 1
+            let total = count_id_in_range(&simplified_ranges, &ids);
+            println!("Fresh ingredients = {}", total);
 
-    // Part 2
-    let total = sum_ids_in_range(&simplified_ranges);
-    println!("Number of valid ids = {total}");
+            // Part 2
+            let total = sum_ids_in_range(&simplified_ranges);
+            println!("Number of valid ids = {total}");
+        }
+        // Compare the lookup strategies, ideally with `--release`
+        Some("bench") => benchmark(args.get(2).map_or(1_000_000, |s| s.parse().unwrap())),
+        // List the input ranges which make each id fresh, e.g. `explain 123 456`
+        Some("explain") => {
+            let (ranges, _) = parse_file("input.txt");
+            let tree = IntervalTree::new(&ranges);
+            for id in args[2..].iter().map(|s| s.parse::<u64>().unwrap()) {
+                let count = tree.count_overlapping(id);
+                if count == 0 {
+                    println!("{} is spoiled", id);
+                    continue;
+                }
+                println!("{} is fresh, covered by {} ranges:", id, count);
+                for i in tree.containing(id) {
+                    let r = &ranges[i];
+                    println!("  line {}: {}-{}", i + 1, r.start(), r.end());
+                }
+            }
+        }
+        // Count fresh ids in a file of any size, e.g. `stream dump.txt`
+        Some("stream") => {
+            let name = args.get(2).map(String::as_str).unwrap_or("input.txt");
+            let reader = BufReader::new(File::open(name).unwrap());
+            let tally = stream_inventory(reader, 1_000_000, |t| {
+                eprintln!("{} ids checked, {} fresh", t.fresh + t.spoiled, t.fresh)
+            });
+            println!("Fresh ingredients = {}", tally.fresh);
+            println!("Spoiled ingredients = {}", tally.spoiled);
+        }
+        Some("spoiled") => {
+            let (ranges, ids) = parse_file("input.txt");
+            let spoiled = RangeSet::new(&ranges).spoiled(&ids);
+            for id in &spoiled {
+                println!("{}", id);
+            }
+            println!("Spoiled ingredients = {}", spoiled.len());
+        }
+        Some("gaps") => {
+            let (ranges, _) = parse_file("input.txt");
+            let gaps = RangeSet::new(&ranges).gaps();
+            for gap in &gaps {
+                println!(
+                    "{}-{} (width {})",
+                    gap.ids.start(),
+                    gap.ids.end(),
+                    gap.width
+                );
+            }
+            println!("Gaps between fresh ranges = {}", gaps.len());
+        }
+        Some("largest-gap") => {
+            let (ranges, _) = parse_file("input.txt");
+            match RangeSet::new(&ranges).largest_gap() {
+                Some(gap) => println!(
+                    "Largest gap = {}-{} (width {})",
+                    gap.ids.start(),
+                    gap.ids.end(),
+                    gap.width
+                ),
+                None => println!("No gaps between fresh ranges"),
+            }
+        }
+        Some("depth") => {
+            let (ranges, _) = parse_file("input.txt");
+            match IntervalTree::new(&ranges).max_depth() {
+                Some((depth, ids)) => println!(
+                    "Maximum coverage depth = {} (first at ids {}-{})",
+                    depth,
+                    ids.start(),
+                    ids.end()
+                ),
+                None => println!("No ranges"),
+            }
+        }
+        Some(other) => panic!("Unrecognised mode {}.", other),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeSet;
 
     #[test]
     fn part_1() {
@@ -98,4 +471,142 @@ mod tests {
         let simplified_ranges = merge_ranges(&ranges);
         assert_eq!(sum_ids_in_range(&simplified_ranges), 353716783056994)
     }
+
+    #[test]
+    fn range_set_lookups() {
+        let set = RangeSet::new(&[10..=14, 3..=5, 16..=20, 12..=18]);
+        assert_eq!(set.ranges, vec![3..=5, 10..=20]);
+        let ids = [1, 3, 5, 6, 9, 10, 17, 20, 21, 32];
+        let fresh: Vec<u64> = ids.iter().copied().filter(|x| set.contains(*x)).collect();
+        assert_eq!(fresh, vec![3, 5, 10, 17, 20]);
+        assert_eq!(set.count_contained(&ids), 5);
+
+        let (ranges, ids) = random_inventory(2000, 11);
+        let set = RangeSet::new(&ranges);
+        let linear = ids
+            .iter()
+            .filter(|x| ranges.iter().any(|r| r.contains(x)))
+            .count() as u64;
+        assert_eq!(count_id_in_range(&ranges, &ids), linear);
+        assert_eq!(set.count_contained(&ids), linear);
+    }
+
+    #[test]
+    fn interval_tree_provenance() {
+        // Includes an empty range, as could be produced by a reversed input line
+        let ranges = [
+            3..=5,
+            10..=14,
+            16..=20,
+            12..=18,
+            RangeInclusive::new(7, 6),
+            14..=14,
+        ];
+        let tree = IntervalTree::new(&ranges);
+        assert_eq!(tree.containing(14), vec![1, 3, 5]);
+        assert_eq!(tree.containing(4), vec![0]);
+        assert_eq!(tree.containing(6), Vec::<usize>::new());
+        assert_eq!(tree.count_overlapping(17), 2);
+        assert_eq!(tree.max_depth(), Some((3, 14..=14)));
+        assert_eq!(IntervalTree::new(&[]).max_depth(), None);
+        assert_eq!(
+            IntervalTree::new(&[0..=u64::MAX]).max_depth(),
+            Some((1, 0..=u64::MAX))
+        );
+
+        let (ranges, ids) = random_inventory(500, 3);
+        let tree = IntervalTree::new(&ranges);
+        for id in ids {
+            let expected: Vec<usize> = (0..ranges.len())
+                .filter(|i| ranges[*i].contains(&id))
+                .collect();
+            assert_eq!(tree.containing(id), expected);
+        }
+    }
+
+    #[test]
+    fn merge_edge_cases() {
+        assert!(merge_ranges(&[]).is_empty());
+        assert_eq!(sum_ids_in_range(&merge_ranges(&[])), 0);
+        assert!(merge_ranges(&[RangeInclusive::new(5, 4)]).is_empty());
+
+        let halves = [u64::MAX / 2 + 1..=u64::MAX, 0..=u64::MAX / 2];
+        let merged = merge_ranges(&halves);
+        assert_eq!(merged, vec![0..=u64::MAX]);
+        assert_eq!(sum_ids_in_range(&merged), 1 << 64);
+        assert_eq!(
+            merge_ranges(&[u64::MAX..=u64::MAX, u64::MAX - 1..=u64::MAX - 1, 0..=0]),
+            vec![0..=0, u64::MAX - 1..=u64::MAX]
+        );
+    }
+
+    /// Compares merging and counting against a set of every covered id, over
+    /// random ranges packed into a small window at either end of the u64 space
+    #[test]
+    fn merge_matches_set_oracle() {
+        let mut state = 99_u64;
+        let mut next = move |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+        for case in 0..1000 {
+            let base = if case % 2 == 0 { 0 } else { u64::MAX - 63 };
+            let ranges: Vec<RangeInclusive<u64>> = (0..next(8))
+                .map(|_| RangeInclusive::new(base + next(64), base + next(64)))
+                .collect();
+            let oracle: BTreeSet<u64> = ranges.iter().flat_map(|r| r.clone()).collect();
+
+            let merged = merge_ranges(&ranges);
+            let covered: BTreeSet<u64> = merged.iter().flat_map(|r| r.clone()).collect();
+            assert_eq!(covered, oracle);
+            assert_eq!(sum_ids_in_range(&merged), oracle.len() as u128);
+            // Merged ranges must be sorted with a gap of at least one id
+            assert!(merged.windows(2).all(|w| w[0].end() + 1 < *w[1].start()));
+
+            let ids: Vec<u64> = (0..64).map(|i| base + i).collect();
+            let set = RangeSet::new(&ranges);
+            assert!(
+                ids.iter()
+                    .all(|id| set.contains(*id) == oracle.contains(id))
+            );
+            assert_eq!(set.count_contained(&ids), oracle.len() as u64);
+        }
+    }
+
+    #[test]
+    fn streaming_matches_parse() {
+        let (_, ids) = parse_file("input.txt");
+        let reader = BufReader::new(File::open("input.txt").unwrap());
+        let tally = stream_inventory(reader, 100, |_| {});
+        assert_eq!(tally.fresh, 615);
+        assert_eq!(tally.fresh + tally.spoiled, ids.len() as u64);
+
+        let mut reports = Vec::new();
+        let input = "3-5\n10-14\n\n1\n5\n8\n11\n17\n";
+        let tally = stream_inventory(input.as_bytes(), 2, |t| reports.push((t.fresh, t.spoiled)));
+        assert_eq!((tally.fresh, tally.spoiled), (2, 3));
+        assert_eq!(reports, vec![(1, 1), (2, 2)]);
+        let tally = stream_inventory(input.as_bytes(), 0, |_| panic!("No reports expected"));
+        assert_eq!((tally.fresh, tally.spoiled), (2, 3));
+    }
+
+    #[test]
+    fn spoiled_and_gaps() {
+        let set = RangeSet::new(&[3..=5, 10..=14, 16..=20, 0..=0, 15..=15, 30..=31]);
+        assert_eq!(set.spoiled(&[17, 1, 5, 8, 32, 0]), vec![1, 8, 32]);
+        assert_eq!(
+            set.gaps(),
+            vec![Gap::new(1..=2), Gap::new(6..=9), Gap::new(21..=29)]
+        );
+        assert_eq!(set.gaps()[1].width, 4);
+        assert_eq!(set.largest_gap(), Some(Gap::new(21..=29)));
+
+        // A single range, or one spanning every id, leaves no gaps between
+        assert_eq!(RangeSet::new(&[0..=u64::MAX]).largest_gap(), None);
+        assert!(RangeSet::new(&[]).gaps().is_empty());
+        let set = RangeSet::new(&[0..=1, u64::MAX..=u64::MAX]);
+        assert_eq!(set.largest_gap().unwrap().width, u64::MAX - 2);
+    }
 }
@@ -1,139 +1,673 @@
 // Solution to day 6 of the Advent of Code challenge
 
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::ops::{Add, Index, IndexMut, Mul, Range};
+use std::str::FromStr;
 
-/// Stores a 2D array within a 1D vector for more efficient value retrival
+/// Stores a 2D array within a 1D vector for more efficient value retrival. The
+/// values are stored row by row and indexed as (row, column).
+#[derive(Clone, Debug, PartialEq)]
 struct Array<T: Copy> {
     data: Vec<T>,
-    dim_1: usize,
-    dim_2: usize,
+    n_rows: usize,
+    n_cols: usize,
 }
 impl<T: Copy> Array<T> {
-    pub fn new(data: Vec<T>, dim_1: usize, dim_2: usize) -> Self {
-        Array { data, dim_1, dim_2 }
+    pub fn new(data: Vec<T>, n_rows: usize, n_cols: usize) -> Self {
+        if data.len() != n_rows * n_cols {
+            panic!("Data length does not match the array dimensions.")
+        }
+        Array {
+            data,
+            n_rows,
+            n_cols,
+        }
+    }
+    /// Creates an array with every element set to the same value
+    pub fn filled(value: T, n_rows: usize, n_cols: usize) -> Self {
+        Array::new(vec![value; n_rows * n_cols], n_rows, n_cols)
+    }
+    pub fn n_rows(&self) -> usize {
+        self.n_rows
+    }
+    pub fn n_cols(&self) -> usize {
+        self.n_cols
+    }
+    /// The values within a single row
+    pub fn row(&self, row: usize) -> &[T] {
+        &self.data[self.n_cols * row..self.n_cols * (row + 1)]
+    }
+    /// Iterates over the values within a single column, from top to bottom
+    pub fn col(&self, col: usize) -> impl Iterator<Item = T> + '_ {
+        if col >= self.n_cols {
+            panic!("Column {} is out of the data range.", col)
+        }
+        self.data.iter().skip(col).step_by(self.n_cols).copied()
+    }
+    /// Iterates over every row, from top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.n_rows).map(|r| self.row(r))
     }
-    fn get(&self, i: usize, j: usize) -> T {
-        if i >= self.dim_1 || j >= self.dim_2 {
+    /// Iterates over every column, from left to right
+    pub fn cols(&self) -> impl Iterator<Item = impl Iterator<Item = T> + '_> {
+        (0..self.n_cols).map(|c| self.col(c))
+    }
+    /// Swaps the rows and columns of the array
+    pub fn transpose(&self) -> Array<T> {
+        let data = self.cols().flatten().collect();
+        Array::new(data, self.n_cols, self.n_rows)
+    }
+    /// Applies a function to every element, keeping the shape of the array
+    pub fn map<U: Copy, F: FnMut(T) -> U>(&self, f: F) -> Array<U> {
+        Array::new(
+            self.data.iter().copied().map(f).collect(),
+            self.n_rows,
+            self.n_cols,
+        )
+    }
+}
+impl<T: Copy> Index<(usize, usize)> for Array<T> {
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        if row >= self.n_rows || col >= self.n_cols {
+            panic!("One or more indices are out of the data range.")
+        }
+        &self.data[self.n_cols * row + col]
+    }
+}
+impl<T: Copy> IndexMut<(usize, usize)> for Array<T> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+        if row >= self.n_rows || col >= self.n_cols {
             panic!("One or more indices are out of the data range.")
         }
-        self.data[self.dim_1 * j + i]
+        &mut self.data[self.n_cols * row + col]
+    }
+}
+impl<T: Copy + fmt::Display> fmt::Display for Array<T> {
+    /// Writes one row per line, with the values right aligned into columns
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .data
+            .iter()
+            .map(|v| v.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for row in self.rows() {
+            let values: Vec<String> = row.iter().map(|v| format!("{:>width$}", v)).collect();
+            writeln!(f, "{}", values.join(" "))?;
+        }
+        Ok(())
     }
 }
 
+/// The operation applied to every value within a problem
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Operation {
     Add,
     Multiply,
+    /// The first value minus all of the others
+    Subtract,
+    Min,
+    Max,
+    Gcd,
+    Lcm,
+    /// Joins the decimal digits of the values, so 12 and 34 gives 1234
+    Concatenate,
+    /// The product of the values modulo the provided modulus
+    ModProduct(u64),
 }
-impl From<char> for Operation {
-    fn from(value: char) -> Self {
-        match value {
-            '+' => Self::Add,
-            '*' => Self::Multiply,
-            _ => panic!("Unrecognised operation {}.", value),
+impl FromStr for Operation {
+    type Err = String;
+
+    /// Parses an operator token, where a modular product is written with its
+    /// modulus, e.g. %97
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "+" => Ok(Self::Add),
+            "*" => Ok(Self::Multiply),
+            "-" => Ok(Self::Subtract),
+            "<" => Ok(Self::Min),
+            ">" => Ok(Self::Max),
+            "g" => Ok(Self::Gcd),
+            "l" => Ok(Self::Lcm),
+            "|" => Ok(Self::Concatenate),
+            _ => match s.strip_prefix('%').map(|m| m.parse::<u64>()) {
+                Some(Ok(modulus)) if modulus > 0 => Ok(Self::ModProduct(modulus)),
+                _ => Err(format!("Unrecognised operation {}.", s)),
+            },
+        }
+    }
+}
+impl Operation {
+    /// Evaluates the operation over the values, failing rather than wrapping if
+    /// the result does not fit within a u64
+    pub fn apply(&self, values: &[u64]) -> Result<u64, EvalError> {
+        let (first, rest) = values.split_first().ok_or(EvalError::NoValues)?;
+        let mut rest = rest.iter().copied();
+        match self {
+            Self::Add => rest.try_fold(*first, |a, b| a.checked_add(b).ok_or(EvalError::Overflow)),
+            Self::Multiply => {
+                rest.try_fold(*first, |a, b| a.checked_mul(b).ok_or(EvalError::Overflow))
+            }
+            Self::Subtract => {
+                rest.try_fold(*first, |a, b| a.checked_sub(b).ok_or(EvalError::Underflow))
+            }
+            Self::Min => Ok(rest.fold(*first, u64::min)),
+            Self::Max => Ok(rest.fold(*first, u64::max)),
+            Self::Gcd => Ok(rest.fold(*first, gcd)),
+            Self::Lcm => rest.try_fold(*first, |a, b| match gcd(a, b) {
+                0 => Ok(0),
+                d => (a / d).checked_mul(b).ok_or(EvalError::Overflow),
+            }),
+            Self::Concatenate => rest.try_fold(*first, |a, b| {
+                10_u64
+                    .checked_pow(n_digits(b))
+                    .and_then(|shift| a.checked_mul(shift))
+                    .and_then(|a| a.checked_add(b))
+                    .ok_or(EvalError::Overflow)
+            }),
+            Self::ModProduct(modulus) => Ok(values.iter().fold(1 % modulus, |a, b| {
+                (a as u128 * *b as u128 % *modulus as u128) as u64
+            })),
+        }
+    }
+    /// Writes the operation applied to the values as an expression, e.g.
+    /// 123 * 45 * 6 or min(4, 2)
+    pub fn render(&self, values: &[u64]) -> String {
+        let join = |separator: &str| {
+            values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<String>>()
+                .join(separator)
+        };
+        match self {
+            Self::Add => join(" + "),
+            Self::Multiply => join(" * "),
+            Self::Subtract => join(" - "),
+            Self::Concatenate => join(" | "),
+            Self::Min => format!("min({})", join(", ")),
+            Self::Max => format!("max({})", join(", ")),
+            Self::Gcd => format!("gcd({})", join(", ")),
+            Self::Lcm => format!("lcm({})", join(", ")),
+            Self::ModProduct(modulus) => format!("({}) mod {}", join(" * "), modulus),
+        }
+    }
+    /// Evaluates the operation over the values with big integers, which is only
+    /// possible for the operations whose result can grow beyond a u64. Each lcm
+    /// step multiplies by the part of the next value not already a factor.
+    fn apply_wide(&self, values: &[u64]) -> Result<BigUint, EvalError> {
+        let (first, rest) = values.split_first().ok_or(EvalError::NoValues)?;
+        let rest = rest.iter().map(|v| BigUint::from(*v));
+        match self {
+            Self::Add => Ok(rest.fold(BigUint::from(*first), |a, b| a + b)),
+            Self::Multiply => Ok(rest.fold(BigUint::from(*first), |a, b| a * b)),
+            Self::Concatenate => Ok(values[1..].iter().fold(BigUint::from(*first), |a, b| {
+                let shifted = (0..n_digits(*b)).fold(a, |a, _| a.mul_small(10));
+                shifted + BigUint::from(*b)
+            })),
+            Self::Lcm => Ok(values[1..]
+                .iter()
+                .fold(BigUint::from(*first), |a, b| match *b {
+                    0 => BigUint::default(),
+                    b => {
+                        let d = gcd(a.rem_u64(b), b);
+                        a * BigUint::from(b / d)
+                    }
+                })),
+            _ => Err(EvalError::Overflow),
+        }
+    }
+    /// Evaluates the operation, falling back to big integers if the result
+    /// overflows a u64
+    pub fn evaluate(&self, values: &[u64]) -> Result<Value, EvalError> {
+        match self.apply(values) {
+            Ok(value) => Ok(Value::Small(value)),
+            Err(EvalError::Overflow) => self.apply_wide(values).map(Value::Wide),
+            Err(error) => Err(error),
         }
     }
 }
 
-/// Read the file and map the values to a custom array type
-fn parse_file(name: &str) -> (Array<u64>, Vec<Operation>) {
-    let file = File::open(name).unwrap();
-    let reader = BufReader::new(file);
+/// Reasons a single problem could not be evaluated
+#[derive(Clone, Debug, PartialEq)]
+enum EvalError {
+    /// The problem has no operator beneath it
+    NoOperation,
+    /// The operator beneath the problem is not recognised
+    UnknownOperation(String),
+    /// A number which is not only digits, such as digits split by a space
+    InvalidNumber(String),
+    NoValues,
+    Overflow,
+    Underflow,
+}
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::NoOperation => write!(f, "no operation"),
+            EvalError::UnknownOperation(token) => write!(f, "unrecognised operation {}", token),
+            EvalError::InvalidNumber(number) => write!(f, "invalid number {:?}", number),
+            EvalError::NoValues => write!(f, "no values to operate on"),
+            EvalError::Overflow => write!(f, "result is too large for a u64"),
+            EvalError::Underflow => write!(f, "result is negative"),
+        }
+    }
+}
 
-    let mut data = Vec::new();
-    let mut ops = Vec::new();
-    let mut n_lines = 0;
-    let operators = ['+', '*'];
+/// The problems of a worksheet which could not be evaluated, given by their
+/// column index
+#[derive(Debug, PartialEq)]
+struct WorksheetError {
+    failures: Vec<(usize, EvalError)>,
+}
 
-    for line in reader.lines() {
-        n_lines += 1;
-        let line_data = line.unwrap();
-        let first_character = &line_data.chars().next().unwrap();
-        if operators.contains(first_character) {
-            ops.extend(line_data.chars().filter(|&x| x != ' ').map(Operation::from));
-        } else {
-            data.extend(line_data.split(" ").filter_map(|x| x.parse::<u64>().ok()));
+/// An unsigned integer of any size, stored as base 2^32 digits with the least
+/// significant digit first and no trailing zero digits
+#[derive(Clone, Debug, Default, PartialEq)]
+struct BigUint {
+    digits: Vec<u32>,
+}
+impl BigUint {
+    fn normalise(mut self) -> Self {
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+        self
+    }
+    /// Multiplies by a single digit
+    fn mul_small(&self, factor: u32) -> BigUint {
+        let mut carry = 0_u64;
+        let mut digits: Vec<u32> = self
+            .digits
+            .iter()
+            .map(|d| {
+                let value = *d as u64 * factor as u64 + carry;
+                carry = value >> 32;
+                value as u32
+            })
+            .collect();
+        digits.push(carry as u32);
+        BigUint { digits }.normalise()
+    }
+    /// Divides by a single digit, returning the quotient and remainder
+    fn div_rem_small(&self, divisor: u32) -> (BigUint, u32) {
+        let mut remainder = 0_u64;
+        let mut digits = vec![0; self.digits.len()];
+        for (i, d) in self.digits.iter().enumerate().rev() {
+            let value = (remainder << 32) | *d as u64;
+            digits[i] = (value / divisor as u64) as u32;
+            remainder = value % divisor as u64;
+        }
+        (BigUint { digits }.normalise(), remainder as u32)
+    }
+    /// The remainder after dividing by a u64
+    fn rem_u64(&self, divisor: u64) -> u64 {
+        self.digits.iter().rev().fold(0, |remainder, d| {
+            (((remainder as u128) << 32 | *d as u128) % divisor as u128) as u64
+        })
+    }
+}
+impl From<u64> for BigUint {
+    fn from(value: u64) -> Self {
+        BigUint {
+            digits: vec![value as u32, (value >> 32) as u32],
         }
+        .normalise()
     }
+}
+impl Add for BigUint {
+    type Output = BigUint;
 
-    (Array::new(data, ops.len(), n_lines - 1), ops)
+    fn add(self, other: BigUint) -> BigUint {
+        let n = self.digits.len().max(other.digits.len());
+        let mut carry = 0_u64;
+        let mut digits: Vec<u32> = (0..n)
+            .map(|i| {
+                let value = *self.digits.get(i).unwrap_or(&0) as u64
+                    + *other.digits.get(i).unwrap_or(&0) as u64
+                    + carry;
+                carry = value >> 32;
+                value as u32
+            })
+            .collect();
+        digits.push(carry as u32);
+        BigUint { digits }.normalise()
+    }
 }
+impl Mul for BigUint {
+    type Output = BigUint;
 
-/// Perform Cephalopod math on the array with required operations
-fn cephalopod_math(data: &Array<u64>, ops: &[Operation]) -> u64 {
-    (0..data.dim_1)
-        .map(|i| {
-            let vec = (0..data.dim_2).map(|j| data.get(i, j));
-            match ops[i] {
-                Operation::Add => vec.sum::<u64>(),
-                Operation::Multiply => vec.product::<u64>(),
+    /// Long multiplication, accumulating each column of partial products
+    fn mul(self, other: BigUint) -> BigUint {
+        let mut digits = vec![0_u32; self.digits.len() + other.digits.len()];
+        for (i, a) in self.digits.iter().enumerate() {
+            let mut carry = 0_u64;
+            for (j, b) in other.digits.iter().enumerate() {
+                let value = *a as u64 * *b as u64 + digits[i + j] as u64 + carry;
+                digits[i + j] = value as u32;
+                carry = value >> 32;
             }
-        })
-        .sum()
+            digits[i + other.digits.len()] = carry as u32;
+        }
+        BigUint { digits }.normalise()
+    }
+}
+impl fmt::Display for BigUint {
+    /// Writes the decimal value by repeatedly splitting off 9 digits at a time
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut chunks = Vec::new();
+        let mut value = self.clone();
+        while !value.digits.is_empty() {
+            let (quotient, remainder) = value.div_rem_small(1_000_000_000);
+            chunks.push(remainder);
+            value = quotient;
+        }
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((first, rest)) => {
+                write!(f, "{}", first)?;
+                rest.iter().rev().try_for_each(|c| write!(f, "{:09}", c))
+            }
+        }
+    }
+}
+
+/// The result of a problem, which is only held as a big integer when it does
+/// not fit within a u64
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Small(u64),
+    Wide(BigUint),
+}
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Small(value) => write!(f, "{}", value),
+            Value::Wide(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// The grand total of a worksheet, along with the column index of every
+/// problem which needed big integer arithmetic
+#[derive(Debug, PartialEq)]
+struct Total {
+    value: BigUint,
+    widened: Vec<usize>,
+}
+
+/// Greatest common divisor, where gcd(a, 0) = a
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// The number of decimal digits needed to write a value
+fn n_digits(value: u64) -> u32 {
+    value.checked_ilog10().unwrap_or(0) + 1
+}
+
+/// Sums the result of each problem, collecting the column of every problem
+/// which failed or needed big integer arithmetic
+fn grand_total(
+    results: impl Iterator<Item = Result<Value, EvalError>>,
+) -> Result<Total, WorksheetError> {
+    let mut total = Total {
+        value: BigUint::default(),
+        widened: Vec::new(),
+    };
+    let mut failures = Vec::new();
+    for (column, result) in results.enumerate() {
+        match result {
+            Ok(Value::Small(value)) => total.value = total.value + BigUint::from(value),
+            Ok(Value::Wide(value)) => {
+                total.value = total.value + value;
+                total.widened.push(column);
+            }
+            Err(error) => failures.push((column, error)),
+        }
+    }
+    if failures.is_empty() {
+        Ok(total)
+    } else {
+        Err(WorksheetError { failures })
+    }
+}
+
+/// Checks whether a line holds operators rather than numbers
+fn is_operator_line(line: &str) -> bool {
+    line.trim_start()
+        .chars()
+        .next()
+        .is_some_and(|c| !c.is_ascii_digit())
+}
+
+/// Which way the numbers of a problem are read from the worksheet
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Reading {
+    /// Each row of a problem holds one number, as humans read it
+    Rows,
+    /// Each character column of a problem holds one number, with the digits
+    /// read top to bottom and the columns taken right to left
+    Columns,
+}
+
+/// A single problem of the worksheet, covering a block of character columns,
+/// with the operator beneath it or the reason it could not be read
+#[derive(Debug, PartialEq)]
+struct Problem {
+    columns: Range<usize>,
+    op: Result<Operation, EvalError>,
+}
+
+/// The worksheet as a grid of characters, with one grid row per line of
+/// numbers, so that the layout of the digits is kept. Problems are blocks of
+/// character columns separated by columns which are blank on every line of
+/// numbers. Each operator token belongs to the last problem starting at or
+/// before its first character, so operators may be wider than their numbers.
+struct Worksheet {
+    grid: Array<char>,
+    problems: Vec<Problem>,
+}
+impl Worksheet {
+    /// Reads the worksheet, where the last line holds the operators. Lines are
+    /// padded with spaces to the longest line, so trailing whitespace does not
+    /// need to be consistent.
+    pub fn parse<R: BufRead>(reader: R) -> Self {
+        let mut lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        while lines.last().is_some_and(|l| l.trim().is_empty()) {
+            lines.pop();
+        }
+        let operator_line = lines.pop().unwrap_or_default();
+        if !is_operator_line(&operator_line) {
+            panic!("The last line should contain the operators.")
+        }
+
+        let width = lines
+            .iter()
+            .chain([&operator_line])
+            .map(|l| l.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut grid = Array::filled(' ', lines.len(), width);
+        for (row, line) in lines.iter().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                grid[(row, col)] = c;
+            }
+        }
+        let is_blank = |i: usize| grid.col(i).all(|c| c == ' ');
+        let mut blocks = Vec::new();
+        let mut start = None;
+        for i in 0..=width {
+            match (start, i < width && !is_blank(i)) {
+                (None, true) => start = Some(i),
+                (Some(s), false) => {
+                    blocks.push(s..i);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        let mut tokens = vec![Vec::new(); blocks.len()];
+        let mut col = 0;
+        for token in operator_line.split(' ') {
+            if !token.is_empty() && !blocks.is_empty() {
+                // Any operator before the first problem belongs to it
+                let problem = blocks.partition_point(|b| b.start <= col).max(1) - 1;
+                tokens[problem].push(token);
+            }
+            col += token.chars().count() + 1;
+        }
+
+        let problems = blocks
+            .into_iter()
+            .zip(tokens)
+            .map(|(columns, tokens)| {
+                let token = tokens.join(" ");
+                let op = match token.as_str() {
+                    "" => Err(EvalError::NoOperation),
+                    _ => token
+                        .parse()
+                        .map_err(|_| EvalError::UnknownOperation(token)),
+                };
+                Problem { columns, op }
+            })
+            .collect();
+        Worksheet { grid, problems }
+    }
+    /// Reads the numbers of a problem in the given order, skipping any rows or
+    /// columns of the problem which are blank. Numbers with spaces between
+    /// their digits are rejected rather than joined together.
+    pub fn numbers(&self, problem: &Problem, reading: Reading) -> Result<Vec<u64>, EvalError> {
+        let values: Vec<String> = match reading {
+            Reading::Rows => self
+                .grid
+                .rows()
+                .map(|row| row[problem.columns.clone()].iter().collect())
+                .collect(),
+            Reading::Columns => problem
+                .columns
+                .clone()
+                .rev()
+                .map(|i| self.grid.col(i).collect())
+                .collect(),
+        };
+        values
+            .iter()
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| EvalError::InvalidNumber(v.to_string()))
+            })
+            .collect()
+    }
+    /// Renders each problem as an expression followed by its result, e.g.
+    /// 123 * 45 * 6 = 33210
+    pub fn explain(&self, reading: Reading) -> Vec<String> {
+        self.problems
+            .iter()
+            .map(|p| {
+                let values = match self.numbers(p, reading) {
+                    Ok(values) => values,
+                    Err(error) => return format!("error, {}", error),
+                };
+                let op = match &p.op {
+                    Ok(op) => op,
+                    Err(error) => {
+                        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                        return format!("{} = error, {}", values.join(" "), error);
+                    }
+                };
+                let expression = op.render(&values);
+                match op.evaluate(&values) {
+                    Ok(Value::Small(result)) => format!("{} = {}", expression, result),
+                    Ok(Value::Wide(result)) => format!("{} = {} (big integer)", expression, result),
+                    Err(error) => format!("{} = error, {}", expression, error),
+                }
+            })
+            .collect()
+    }
+    /// Evaluates every problem and sums their results
+    pub fn evaluate(&self, reading: Reading) -> Result<Total, WorksheetError> {
+        grand_total(
+            self.problems
+                .iter()
+                .map(|p| p.op.clone()?.evaluate(&self.numbers(p, reading)?)),
+        )
+    }
 }
 
-/// Read the file and map the values to a vector of vectors, where each
-/// sub-vector contains the values that should be used within a particular
-/// computation
-fn parse_file_2(name: &str) -> (Vec<Vec<u64>>, Vec<Operation>) {
+/// Read the file into a worksheet which can be read either way
+fn parse_file(name: &str) -> Worksheet {
     let file = File::open(name).unwrap();
-    let reader = BufReader::new(file);
-
-    let mut data = Vec::new();
-    let mut ops = Vec::new();
-    let operators = ['+', '*'];
-
-    // The following is probably not ideal, but works for now.
-    for line in reader.lines() {
-        let line_data = line.unwrap();
-        let first_character = &line_data.chars().next().unwrap();
-        if operators.contains(first_character) {
-            ops.extend(line_data.chars().filter(|&x| x != ' ').map(Operation::from));
-        } else if data.is_empty() {
-            data.extend(line_data.chars().map(String::from));
-        } else {
-            data.iter_mut()
-                .zip(line_data.chars())
-                .for_each(|(s, c)| s.push(c));
-        }
-    }
-    let mut c1 = 0;
-    let mut conv_data = vec![Vec::new(); ops.len()];
-    for value in data {
-        let new_value = value.replace(" ", "");
-        if new_value.is_empty() {
-            c1 += 1;
-        } else {
-            conv_data[c1].push(new_value.parse().unwrap());
-        }
-    }
-
-    (conv_data, ops)
-}
-
-/// Perform Cephalopod math for part 2, in this case we take advantage of the
-/// fact that the parse file naturally returns a vector of values for each
-/// corresponding operation.
-fn cephalopod_math_2(data: &[Vec<u64>], ops: &[Operation]) -> u64 {
-    data.iter()
-        .zip(ops)
-        .map(|(value, op)| match op {
-            Operation::Add => value.iter().sum::<u64>(),
-            Operation::Multiply => value.iter().product::<u64>(),
-        })
-        .sum()
+    Worksheet::parse(BufReader::new(file))
+}
+
+/// Perform Cephalopod math on the worksheet, reading each row as a number
+fn cephalopod_math(worksheet: &Worksheet) -> Result<Total, WorksheetError> {
+    worksheet.evaluate(Reading::Rows)
+}
+
+/// Perform Cephalopod math for part 2, where numbers are instead written down
+/// the columns and read from right to left
+fn cephalopod_math_2(worksheet: &Worksheet) -> Result<Total, WorksheetError> {
+    worksheet.evaluate(Reading::Columns)
+}
+
+/// Prints the grand total, or the reason each failing problem could not be
+/// evaluated
+fn report_total(label: &str, total: Result<Total, WorksheetError>) {
+    match total {
+        Ok(total) => {
+            println!("{} = {}", label, total.value);
+            for column in total.widened {
+                println!("{}: problem {} needed big integers", label, column + 1);
+            }
+        }
+        Err(error) => {
+            for (column, error) in error.failures {
+                println!("{}: problem {} failed, {}", label, column + 1, error);
+            }
+        }
+    }
 }
 
 fn main() {
-    // Part 1
-    let (data, ops) = parse_file("input.txt");
-    let total = cephalopod_math(&data, &ops);
-    println!("Grand total = {}", total);
+    let worksheet = parse_file("input.txt");
+
+    match env::args().nth(1).as_deref() {
+        None => {
+            // Part 1
+            report_total("Grand total", cephalopod_math(&worksheet));
 
-    // Part 2
-    let (data, ops) = parse_file_2("input.txt");
-    let total = cephalopod_math_2(&data, &ops);
-    println!("Grand total (2) = {}", total);
+            // Part 2
+            report_total("Grand total (2)", cephalopod_math_2(&worksheet));
+        }
+        // Show how every problem is read in both orders
+        Some("explain") => {
+            let rows = worksheet.explain(Reading::Rows);
+            let columns = worksheet.explain(Reading::Columns);
+            for (i, (row, column)) in rows.iter().zip(&columns).enumerate() {
+                println!("Problem {}", i + 1);
+                println!("  rows:    {}", row);
+                println!("  columns: {}", column);
+            }
+        }
+        // Show the worksheet as parsed, and as read one column at a time
+        Some("grid") => {
+            let visible = worksheet.grid.map(|c| if c == ' ' { '.' } else { c });
+            println!("{} x {} characters", visible.n_rows(), visible.n_cols());
+            println!("{}", visible);
+            println!("{}", visible.transpose());
+        }
+        Some(other) => panic!("Unrecognised mode {}.", other),
+    }
 }
 
 #[cfg(test)]
@@ -142,13 +676,214 @@ mod tests {
 
     #[test]
     fn part_1() {
-        let (data, ops) = parse_file("input.txt");
-        assert_eq!(cephalopod_math(&data, &ops), 6172481852142)
+        let worksheet = parse_file("input.txt");
+        let total = cephalopod_math(&worksheet).unwrap();
+        assert_eq!(total.value, BigUint::from(6172481852142))
     }
 
     #[test]
     fn part_2() {
-        let (data, ops) = parse_file_2("input.txt");
-        assert_eq!(cephalopod_math_2(&data, &ops), 10188206723429)
+        let worksheet = parse_file("input.txt");
+        let total = cephalopod_math_2(&worksheet).unwrap();
+        assert_eq!(total.value, BigUint::from(10188206723429))
+    }
+
+    #[test]
+    fn operations() {
+        let apply = |op: &str, values: &[u64]| op.parse::<Operation>().unwrap().apply(values);
+        assert_eq!(apply("-", &[10, 3, 2]), Ok(5));
+        assert_eq!(apply("-", &[1, 2]), Err(EvalError::Underflow));
+        assert_eq!(apply("<", &[4, 2, 9]), Ok(2));
+        assert_eq!(apply(">", &[4, 2, 9]), Ok(9));
+        assert_eq!(apply("g", &[12, 18, 8]), Ok(2));
+        assert_eq!(apply("l", &[4, 6, 10]), Ok(60));
+        assert_eq!(apply("|", &[12, 0, 345]), Ok(120345));
+        assert_eq!(apply("%7", &[3, 4, 5]), Ok(4));
+        assert_eq!(apply("*", &[u64::MAX, 2]), Err(EvalError::Overflow));
+        assert_eq!(apply("|", &[u64::MAX / 10, 12]), Err(EvalError::Overflow));
+        assert_eq!(apply("%1000", &[u64::MAX, u64::MAX]), Ok(225));
+        assert_eq!(apply("+", &[]), Err(EvalError::NoValues));
+        assert!("%0".parse::<Operation>().is_err());
+        assert!("/".parse::<Operation>().is_err());
+
+        // Overflowing problems fall back to big integers, while other
+        // failures are still reported
+        let text = format!("{} 3 2\n{:>20} 2 3\n{:<20} + -\n", u64::MAX, 2, 'l');
+        let worksheet = Worksheet::parse(text.as_bytes());
+        assert_eq!(
+            cephalopod_math(&worksheet),
+            Err(WorksheetError {
+                failures: vec![(2, EvalError::Underflow)]
+            })
+        );
+        let worksheet = Worksheet::parse(text.replace('-', "+").as_bytes());
+        let total = cephalopod_math(&worksheet).unwrap();
+        assert_eq!(total.value.to_string(), "36893488147419103240");
+        assert_eq!(total.widened, vec![0]);
+        let worksheet = Worksheet::parse(text.replace('l', ">").replace('-', "+").as_bytes());
+        let total = cephalopod_math(&worksheet).unwrap();
+        assert_eq!(total.value.to_string(), "18446744073709551625");
+        assert!(total.widened.is_empty());
+    }
+
+    #[test]
+    fn big_integer_fallback() {
+        let evaluate = |op: &str, values: &[u64]| {
+            let value = op.parse::<Operation>().unwrap().evaluate(values).unwrap();
+            (value.to_string(), matches!(value, Value::Wide(_)))
+        };
+        assert_eq!(evaluate("*", &[99, 3]), ("297".to_string(), false));
+        assert_eq!(
+            evaluate("*", &[u64::MAX, u64::MAX, 10]),
+            ("3402823669209384634264811192843491082250".to_string(), true)
+        );
+        assert_eq!(
+            evaluate("+", &[u64::MAX, u64::MAX]),
+            ("36893488147419103230".to_string(), true)
+        );
+        assert_eq!(
+            evaluate("|", &[u64::MAX, 0, 12]),
+            ("18446744073709551615012".to_string(), true)
+        );
+        assert_eq!(
+            evaluate("l", &[u64::MAX, u64::MAX - 1, 6]),
+            ("340282366920938463408034375210639556610".to_string(), true)
+        );
+        assert_eq!(BigUint::default().to_string(), "0");
+        assert_eq!(BigUint::from(1_000_000_000).to_string(), "1000000000");
+
+        let text = format!("{} 3\n{:>20} 2\n{:<20} *\n", u64::MAX, 2, '*');
+        let total = cephalopod_math(&Worksheet::parse(text.as_bytes())).unwrap();
+        assert_eq!(total.value.to_string(), "36893488147419103236");
+        assert_eq!(total.widened, vec![0]);
+    }
+
+    #[test]
+    fn ragged_trailing_whitespace() {
+        // Trailing spaces are stripped from some lines, which must not change
+        // how the columns of the final problem line up
+        let text = "123 328  51 64\n 45 64  387 23 \n  6 98  215 314\n*   +   *   +\n";
+        let worksheet = Worksheet::parse(text.as_bytes());
+        assert_eq!(worksheet.problems.len(), 4);
+        assert_eq!(
+            worksheet.problems[3],
+            Problem {
+                columns: 12..15,
+                op: Ok(Operation::Add)
+            }
+        );
+        assert_eq!(
+            worksheet.numbers(&worksheet.problems[0], Reading::Rows),
+            Ok(vec![123, 45, 6])
+        );
+        assert_eq!(
+            worksheet.numbers(&worksheet.problems[3], Reading::Columns),
+            Ok(vec![4, 431, 623])
+        );
+        let total = cephalopod_math(&worksheet).unwrap();
+        assert_eq!(total.value, BigUint::from(4277556));
+        let total = cephalopod_math_2(&worksheet).unwrap();
+        assert_eq!(total.value, BigUint::from(3263827));
+    }
+
+    #[test]
+    fn wide_operators() {
+        // An operator wider than its numbers does not join the next problem
+        let worksheet = Worksheet::parse("1 2\n%97 +\n".as_bytes());
+        assert_eq!(
+            worksheet.problems,
+            vec![
+                Problem {
+                    columns: 0..1,
+                    op: Ok(Operation::ModProduct(97))
+                },
+                Problem {
+                    columns: 2..3,
+                    op: Ok(Operation::Add)
+                }
+            ]
+        );
+        assert_eq!(cephalopod_math(&worksheet).unwrap().value, BigUint::from(3));
+
+        // Digits split by a space within one row are rejected
+        let worksheet = Worksheet::parse("12\n3 4\n*\n".as_bytes());
+        assert_eq!(
+            cephalopod_math(&worksheet),
+            Err(WorksheetError {
+                failures: vec![(0, EvalError::InvalidNumber("3 4".to_string()))]
+            })
+        );
+    }
+
+    #[test]
+    fn explain() {
+        let text = "123 328  51 64\n 45 64  387 23\n  6 98  215 314\n*   +   *   %7\n";
+        let worksheet = Worksheet::parse(text.as_bytes());
+        assert_eq!(
+            worksheet.explain(Reading::Rows),
+            vec![
+                "123 * 45 * 6 = 33210",
+                "328 + 64 + 98 = 490",
+                "51 * 387 * 215 = 4243455",
+                "(64 * 23 * 314) mod 7 = 5"
+            ]
+        );
+        assert_eq!(
+            worksheet.explain(Reading::Columns)[0],
+            "356 * 24 * 1 = 8544"
+        );
+
+        let worksheet = Worksheet::parse("1 9\n-  <\n".as_bytes());
+        assert_eq!(
+            worksheet.explain(Reading::Rows),
+            vec!["1 = 1", "min(9) = 9"]
+        );
+        let worksheet = Worksheet::parse("1\n2\n-\n".as_bytes());
+        assert_eq!(
+            worksheet.explain(Reading::Rows),
+            vec!["1 - 2 = error, result is negative"]
+        );
+
+        // Unknown and missing operators fail their own problem only
+        let worksheet = Worksheet::parse("1 2 3\n4 5 6\n/ +\n".as_bytes());
+        assert_eq!(
+            worksheet.explain(Reading::Rows),
+            vec![
+                "1 4 = error, unrecognised operation /",
+                "2 + 5 = 7",
+                "3 6 = error, no operation"
+            ]
+        );
+        assert_eq!(
+            cephalopod_math(&worksheet),
+            Err(WorksheetError {
+                failures: vec![
+                    (0, EvalError::UnknownOperation("/".to_string())),
+                    (2, EvalError::NoOperation)
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn array() {
+        let mut array = Array::new(vec![1, 2, 3, 4, 5, 6], 2, 3);
+        assert_eq!((array.n_rows(), array.n_cols()), (2, 3));
+        assert_eq!(array[(1, 0)], 4);
+        assert_eq!(array[(0, 2)], 3);
+        assert_eq!(array.row(1), &[4, 5, 6]);
+        assert_eq!(array.col(1).collect::<Vec<_>>(), vec![2, 5]);
+        assert_eq!(
+            array.cols().map(|c| c.sum()).collect::<Vec<u64>>(),
+            vec![5, 7, 9]
+        );
+
+        array[(0, 1)] = 20;
+        let transposed = array.transpose();
+        assert_eq!(transposed, Array::new(vec![1, 4, 20, 5, 3, 6], 3, 2));
+        assert_eq!(transposed.transpose(), array);
+        assert_eq!(array.map(|v| v % 2 == 0).row(0), &[false, true, false]);
+        assert_eq!(array.to_string(), " 1 20  3\n 4  5  6\n");
+        assert_eq!(Array::<u8>::new(vec![], 0, 4).transpose().n_rows(), 4);
     }
 }
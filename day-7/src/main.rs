@@ -1,105 +1,911 @@
 // Solution to day 7 of the Advent of Code challenge
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
 use std::fs::File;
-use std::hash::Hash;
 use std::io::{BufRead, BufReader};
-use std::ops::AddAssign;
+use std::str::FromStr;
+use std::time::Instant;
 
-/// Read the file, storing each row of beamsplitters within a vector, and also
-/// returning the start positions as a set
-fn parse_file(name: &str) -> (Vec<Vec<bool>>, HashSet<usize>) {
-    let file = File::open(name).unwrap();
-    let reader = BufReader::new(file);
+/// A single cell of the manifold
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Cell {
+    Empty,
+    /// Emits a beam travelling down, and is otherwise empty
+    Start,
+    /// Splits a vertical beam into two beams one column either side, while
+    /// horizontal beams pass straight through
+    Splitter,
+    /// Mirror `/`, turning a beam travelling down to the left
+    ForwardMirror,
+    /// Mirror `\`, turning a beam travelling down to the right
+    BackMirror,
+    /// Stops any beam reaching it
+    Absorber,
+    /// Joins every beam reaching it into a single beam travelling down,
+    /// collapsing their timelines into one
+    Merger,
+}
+impl TryFrom<char> for Cell {
+    type Error = char;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        match c {
+            '.' => Ok(Cell::Empty),
+            'S' => Ok(Cell::Start),
+            '^' => Ok(Cell::Splitter),
+            '/' => Ok(Cell::ForwardMirror),
+            '\\' => Ok(Cell::BackMirror),
+            '#' => Ok(Cell::Absorber),
+            'o' => Ok(Cell::Merger),
+            _ => Err(c),
+        }
+    }
+}
+
+/// The direction a beam is travelling in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    /// The change in row and column for one step in this direction
+    fn delta(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (-1, 0),
+            Direction::Down => (1, 0),
+            Direction::Left => (0, -1),
+            Direction::Right => (0, 1),
+        }
+    }
+}
+
+/// Where a beam ends up after leaving a cell
+enum Entry {
+    /// The beam enters the cell with the given state index
+    State(usize),
+    /// The beam leaves through the bottom of the manifold in the given column
+    Exit(usize),
+    /// The beam leaves through the top, or the sides when the edge policy
+    /// loses it
+    Lost,
+}
 
-    let mut beamsplitters: Vec<Vec<bool>> = Vec::new();
-    let mut initial_positions: Option<HashSet<usize>> = None;
+/// Returned when beams can travel around a loop, so have no finite number of
+/// timelines
+#[derive(Debug, PartialEq)]
+struct CycleError {
+    states: usize,
+}
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Beams loop through {} cell states", self.states)
+    }
+}
 
-    for (i, line) in reader.lines().enumerate() {
-        if i == 0 {
-            initial_positions = Some(HashSet::from_iter(
+/// Returned when a manifold has cells other than splitters, or starts below the
+/// first row, so cannot be treated as rows of splitters
+#[derive(Debug, PartialEq)]
+struct UnsupportedLayout;
+impl fmt::Display for UnsupportedLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The manifold must only have splitters, with every start on the first row"
+        )
+    }
+}
+
+/// The full grid of the manifold, with shorter rows padded with empty cells
+struct Manifold {
+    cells: Vec<Vec<Cell>>,
+    n_rows: usize,
+    n_cols: usize,
+}
+impl Manifold {
+    /// Reads every row of the manifold, panicking on unrecognised cells
+    fn parse(reader: impl BufRead) -> Manifold {
+        let mut cells: Vec<Vec<Cell>> = reader
+            .lines()
+            .enumerate()
+            .map(|(row, line)| {
                 line.unwrap()
                     .chars()
                     .enumerate()
-                    .filter_map(|(i, c)| match c {
-                        'S' => Some(i),
-                        _ => None,
-                    }),
-            ))
-        } else if i % 2 == 0 {
-            beamsplitters.push(line.unwrap().chars().map(|c| matches!(c, '^')).collect());
+                    .map(|(col, c)| {
+                        Cell::try_from(c).unwrap_or_else(|c| {
+                            panic!("Unrecognised cell {} at row {}, column {}.", c, row, col)
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        let n_rows = cells.len();
+        let n_cols = cells.iter().map(Vec::len).max().unwrap_or(0);
+        for row in cells.iter_mut() {
+            row.resize(n_cols, Cell::Empty);
+        }
+        Manifold {
+            cells,
+            n_rows,
+            n_cols,
+        }
+    }
+
+    /// Finds the positions of every start cell
+    fn starts(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.cells.iter().enumerate().flat_map(|(row, cells)| {
+            cells
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == Cell::Start)
+                .map(move |(col, _)| (row, col))
+        })
+    }
+
+    /// For a manifold of only splitters with every start on the first row,
+    /// returns each row below the first as splitter flags along with the
+    /// start columns, so it can be propagated densely
+    fn beamsplitter_rows(&self) -> Result<(Vec<Vec<bool>>, HashSet<usize>), UnsupportedLayout> {
+        let simple = self
+            .cells
+            .iter()
+            .flatten()
+            .all(|c| matches!(c, Cell::Empty | Cell::Start | Cell::Splitter));
+        if !simple || self.starts().any(|(row, _)| row > 0) {
+            return Err(UnsupportedLayout);
+        }
+        let beamsplitters = self
+            .cells
+            .iter()
+            .skip(1)
+            .map(|row| row.iter().map(|c| *c == Cell::Splitter).collect())
+            .collect();
+        Ok((beamsplitters, self.starts().map(|(_, col)| col).collect()))
+    }
+
+    /// Index of the state of a beam entering a cell in a direction. All
+    /// beams entering a merger share one state.
+    fn state(&self, row: usize, col: usize, direction: Direction) -> usize {
+        let direction = match self.cells[row][col] {
+            Cell::Merger => Direction::Down,
+            _ => direction,
+        };
+        (row * self.n_cols + col) * 4 + direction as usize
+    }
+
+    /// Finds where a beam moving in a direction from a position ends up.
+    /// Beams moving past the sides are handled by the edge policy, with
+    /// horizontal beams reflected back the way they came.
+    fn enter(&self, row: isize, col: isize, direction: Direction, policy: EdgePolicy) -> Entry {
+        let (d_row, d_col) = direction.delta();
+        let (row, col) = (row + d_row, col + d_col);
+        let (col, direction) = if col >= 0 && (col as usize) < self.n_cols {
+            (col as usize, direction)
+        } else {
+            let right = col >= 0;
+            let edge = if right { self.n_cols - 1 } else { 0 };
+            let direction = match (policy, direction) {
+                (EdgePolicy::Reflect, Direction::Left) => Direction::Right,
+                (EdgePolicy::Reflect, Direction::Right) => Direction::Left,
+                _ => direction,
+            };
+            match policy.target(edge, right, self.n_cols) {
+                Some(col) => (col, direction),
+                None => return Entry::Lost,
+            }
+        };
+        if row < 0 {
+            Entry::Lost
+        } else if row as usize >= self.n_rows {
+            Entry::Exit(col)
+        } else {
+            Entry::State(self.state(row as usize, col, direction))
+        }
+    }
+
+    /// Finds where the beams leaving a cell state end up
+    fn successors(&self, state: usize, policy: EdgePolicy) -> Vec<Entry> {
+        let direction = Direction::ALL[state % 4];
+        let (row, col) = (state / 4 / self.n_cols, state / 4 % self.n_cols);
+        let (row, col) = (row as isize, col as isize);
+        let turned = |direction| vec![self.enter(row, col, direction, policy)];
+        match (self.cells[row as usize][col as usize], direction) {
+            (Cell::Empty | Cell::Start, _) => turned(direction),
+            (Cell::Splitter, Direction::Up | Direction::Down) => vec![
+                self.enter(row, col - 1, direction, policy),
+                self.enter(row, col + 1, direction, policy),
+            ],
+            (Cell::Splitter, _) => turned(direction),
+            (Cell::ForwardMirror, Direction::Down) => turned(Direction::Left),
+            (Cell::ForwardMirror, Direction::Left) => turned(Direction::Down),
+            (Cell::ForwardMirror, Direction::Up) => turned(Direction::Right),
+            (Cell::ForwardMirror, Direction::Right) => turned(Direction::Up),
+            (Cell::BackMirror, Direction::Down) => turned(Direction::Right),
+            (Cell::BackMirror, Direction::Right) => turned(Direction::Down),
+            (Cell::BackMirror, Direction::Up) => turned(Direction::Left),
+            (Cell::BackMirror, Direction::Left) => turned(Direction::Up),
+            (Cell::Absorber, _) => Vec::new(),
+            (Cell::Merger, _) => turned(Direction::Down),
+        }
+    }
+
+    /// Propagates beams cell by cell through any layout. Each state is a beam
+    /// entering a cell in a direction, and states are visited in topological
+    /// order so the number of timelines reaching a state is complete before
+    /// it is passed on. Beams leaving through the top are lost, and those
+    /// leaving through the sides are handled by the edge policy. The visitor
+    /// is given the row, column, direction and timelines of each beam
+    /// entering a cell.
+    fn simulate_cells(
+        &self,
+        policy: EdgePolicy,
+        mut visit: impl FnMut(usize, usize, Direction, u64),
+    ) -> Result<Propagation, CycleError> {
+        let mut propagation = Propagation {
+            exits: vec![0; self.n_cols],
+            ..Default::default()
+        };
+        let mut timelines = vec![0_u64; self.n_rows * self.n_cols * 4];
+        let mut in_degree = vec![0_usize; timelines.len()];
+        let mut reached = vec![false; timelines.len()];
+
+        // Find every state reachable from the starts, and how many beams lead
+        // into each of them
+        let mut stack = Vec::new();
+        for (row, col) in self.starts() {
+            match self.enter(row as isize, col as isize, Direction::Down, policy) {
+                Entry::State(state) => {
+                    timelines[state] += 1;
+                    stack.push(state);
+                }
+                Entry::Exit(col) => propagation.exits[col] += 1,
+                Entry::Lost => unreachable!(),
+            }
+        }
+        let mut n_reached = 0;
+        while let Some(state) = stack.pop() {
+            if std::mem::replace(&mut reached[state], true) {
+                continue;
+            }
+            n_reached += 1;
+            for entry in self.successors(state, policy) {
+                if let Entry::State(next) = entry {
+                    in_degree[next] += 1;
+                    stack.push(next);
+                }
+            }
+        }
+
+        let mut ready: Vec<usize> = (0..timelines.len())
+            .filter(|s| reached[*s] && in_degree[*s] == 0)
+            .collect();
+        let mut split = HashSet::new();
+        let mut n_processed = 0;
+        while let Some(state) = ready.pop() {
+            n_processed += 1;
+            let cell = (state / 4 / self.n_cols, state / 4 % self.n_cols);
+            visit(cell.0, cell.1, Direction::ALL[state % 4], timelines[state]);
+            let count = match self.cells[cell.0][cell.1] {
+                Cell::Merger => timelines[state].min(1),
+                Cell::Absorber => {
+                    propagation.timelines_absorbed += timelines[state];
+                    continue;
+                }
+                _ => timelines[state],
+            };
+            let successors = self.successors(state, policy);
+            if self.cells[cell.0][cell.1] == Cell::Splitter && successors.len() == 2 {
+                split.insert(cell);
+            }
+            for entry in successors {
+                match entry {
+                    Entry::State(next) => {
+                        timelines[next] += count;
+                        in_degree[next] -= 1;
+                        if in_degree[next] == 0 {
+                            ready.push(next);
+                        }
+                    }
+                    Entry::Exit(col) => propagation.exits[col] += count,
+                    Entry::Lost => {
+                        propagation.beams_lost += 1;
+                        propagation.timelines_lost += count;
+                    }
+                }
+            }
+        }
+        if n_processed < n_reached {
+            return Err(CycleError {
+                states: n_reached - n_processed,
+            });
         }
+        propagation.splits = split.len() as u32;
+        propagation.timelines = propagation.exits.iter().sum();
+        Ok(propagation)
     }
-    (beamsplitters, initial_positions.unwrap())
+
+    /// Propagates beams through the manifold, using dense rows when the layout
+    /// only has splitters and simulating cell by cell otherwise
+    fn simulate(&self, policy: EdgePolicy) -> Result<Propagation, CycleError> {
+        self.simulate_with(policy, |_, _, _, _| {})
+    }
+
+    /// Simulates the manifold, giving the visitor the row, column, direction
+    /// and timelines of each beam entering a cell
+    fn simulate_with(
+        &self,
+        policy: EdgePolicy,
+        mut visit: impl FnMut(usize, usize, Direction, u64),
+    ) -> Result<Propagation, CycleError> {
+        match self.beamsplitter_rows() {
+            // Dense rows start from the second row of the manifold
+            Ok((beamsplitters, initial_positions)) => Ok(propagate_with(
+                &beamsplitters,
+                &initial_positions,
+                policy,
+                |row, col, count| visit(row + 1, col, Direction::Down, count),
+            )),
+            Err(UnsupportedLayout) => self.simulate_cells(policy, visit),
+        }
+    }
+
+    /// The character representing each cell in the input
+    fn symbol(&self, row: usize, col: usize) -> char {
+        match self.cells[row][col] {
+            Cell::Empty => '.',
+            Cell::Start => 'S',
+            Cell::Splitter => '^',
+            Cell::ForwardMirror => '/',
+            Cell::BackMirror => '\\',
+            Cell::Absorber => '#',
+            Cell::Merger => 'o',
+        }
+    }
+}
+
+/// Read the full manifold from a file
+fn parse_file(name: &str) -> Manifold {
+    let file = File::open(name).unwrap();
+    Manifold::parse(BufReader::new(file))
 }
 
-/// From a set of beams and rows of beam splitters, calculate the new beam
-/// splitter positions after each row and track the new of splittings which
-/// occur
-fn count_number_of_splittings(
-    beamsplitters: &Vec<Vec<bool>>,
+/// What happens to a beam which is split beyond the edge of the manifold
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EdgePolicy {
+    /// The beam leaves the manifold and is counted as lost
+    Lost,
+    /// The beam bounces off the wall back into the edge column
+    Reflect,
+    /// The beam re-enters on the opposite side of the manifold
+    Wrap,
+}
+impl EdgePolicy {
+    /// Finds the column a beam moves to when shifted one column left or right
+    /// of its position, or None if it is lost
+    fn target(&self, pos: usize, right: bool, width: usize) -> Option<usize> {
+        let shifted = if right {
+            pos.checked_add(1)
+        } else {
+            pos.checked_sub(1)
+        };
+        match (shifted.filter(|p| *p < width), self) {
+            (Some(p), _) => Some(p),
+            (None, EdgePolicy::Lost) => None,
+            (None, EdgePolicy::Reflect) => Some(pos),
+            (None, EdgePolicy::Wrap) => Some(if right { 0 } else { width - 1 }),
+        }
+    }
+}
+impl FromStr for EdgePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lost" => Ok(EdgePolicy::Lost),
+            "reflect" => Ok(EdgePolicy::Reflect),
+            "wrap" => Ok(EdgePolicy::Wrap),
+            _ => Err(format!("Unrecognised edge policy {}.", s)),
+        }
+    }
+}
+
+/// Totals found by propagating beams through every row of the manifold. Beams
+/// which leave the manifold at the sides are counted once per splitter side in
+/// `beams_lost`, and `timelines_lost` holds the timelines they carried.
+/// Timelines ending in an absorber are counted in `timelines_absorbed`, and
+/// `exits` holds the number of timelines leaving the bottom in each column.
+#[derive(Debug, Default, PartialEq)]
+struct Propagation {
+    splits: u32,
+    timelines: u64,
+    beams_lost: u32,
+    timelines_lost: u64,
+    timelines_absorbed: u64,
+    exits: Vec<u64>,
+}
+
+/// Propagates the beams down the manifold one row at a time. The number of
+/// timelines reaching each column is held in a dense row, with a second row
+/// being filled for the next step and the two swapped, so no allocation is
+/// needed per row. A split is counted once per splitter which is reached by
+/// any timeline, and splits beyond the sides are handled by the edge policy.
+fn propagate(
+    beamsplitters: &[Vec<bool>],
     initial_positions: &HashSet<usize>,
-) -> u32 {
-    let mut positions = initial_positions.clone();
-    let mut total = 0;
-    for row in beamsplitters {
-        let mut new_timelines = HashSet::new();
-        for pos in positions {
-            if row[pos] {
-                new_timelines.extend([pos - 1, pos + 1]);
-                total += 1;
-            } else {
-                new_timelines.insert(pos);
-            }
-        }
-        positions = new_timelines;
-    }
-    total
-}
-
-/// Takes a hashmap and modify the existing value if the key exists, otherwise
-/// adds the key/value to the map.
-fn add_to_or_update_hashmap<K: Eq + Hash, V: AddAssign + Copy>(
-    map: &mut HashMap<K, V>,
-    key: K,
-    value: V,
-) {
-    map.entry(key).and_modify(|v| *v += value).or_insert(value);
-}
-
-/// Counts the number of timelines created by beams passing through a number of
-/// splitters from a set of starting positions. A dictionary is used to count
-/// the number of ways a position is reached by a path after each iteration.
-fn count_number_of_timelines(
-    beamsplitters: &Vec<Vec<bool>>,
+    policy: EdgePolicy,
+) -> Propagation {
+    propagate_with(beamsplitters, initial_positions, policy, |_, _, _| {})
+}
+
+/// Propagates the beams as in `propagate`, giving the visitor the row, column
+/// and timelines of each beam entering a row
+fn propagate_with(
+    beamsplitters: &[Vec<bool>],
     initial_positions: &HashSet<usize>,
-) -> u64 {
-    let mut timelines: HashMap<usize, u64> = initial_positions.iter().map(|k| (*k, 1)).collect();
-    for row in beamsplitters {
-        let mut new_timelines = HashMap::new();
-        for (pos, count) in timelines {
-            if row[pos] {
-                add_to_or_update_hashmap(&mut new_timelines, pos - 1, count);
-                add_to_or_update_hashmap(&mut new_timelines, pos + 1, count);
-            } else {
-                add_to_or_update_hashmap(&mut new_timelines, pos, count);
+    policy: EdgePolicy,
+    mut visit: impl FnMut(usize, usize, u64),
+) -> Propagation {
+    let width = beamsplitters
+        .first()
+        .map_or(0, |r| r.len())
+        .max(initial_positions.iter().max().map_or(0, |p| p + 1));
+    let mut current = vec![0_u64; width];
+    let mut next = vec![0_u64; width];
+    for pos in initial_positions {
+        current[*pos] = 1;
+    }
+
+    let mut propagation = Propagation::default();
+    for (i, row) in beamsplitters.iter().enumerate() {
+        next.fill(0);
+        for (pos, count) in current.iter().enumerate().filter(|(_, c)| **c > 0) {
+            visit(i, pos, *count);
+            // Rows shorter than the manifold are treated as empty beyond their end
+            if !row.get(pos).copied().unwrap_or(false) {
+                next[pos] += count;
+                continue;
+            }
+            propagation.splits += 1;
+            for right in [false, true] {
+                match policy.target(pos, right, width) {
+                    Some(target) => next[target] += count,
+                    None => {
+                        propagation.beams_lost += 1;
+                        propagation.timelines_lost += count;
+                    }
+                }
+            }
+        }
+        std::mem::swap(&mut current, &mut next);
+    }
+    propagation.timelines = current.iter().sum();
+    propagation.exits = current;
+    propagation
+}
+
+/// Propagates the beams from the start of the manifold and counts the number
+/// of splitters which split a beam
+fn count_number_of_splittings(manifold: &Manifold, policy: EdgePolicy) -> u32 {
+    manifold.simulate(policy).unwrap().splits
+}
+
+/// Counts the number of timelines created by beams passing through the
+/// manifold from its starts, by tracking the number of ways each cell is
+/// reached by a path.
+fn count_number_of_timelines(manifold: &Manifold, policy: EdgePolicy) -> u64 {
+    manifold.simulate(policy).unwrap().timelines
+}
+
+/// The manifold with the beams from one propagation drawn over it
+struct Diagram<'a> {
+    manifold: &'a Manifold,
+    /// Whether a vertical and a horizontal beam enter each cell
+    beams: Vec<Vec<(bool, bool)>>,
+    propagation: Propagation,
+}
+impl<'a> Diagram<'a> {
+    fn new(manifold: &'a Manifold) -> Result<Diagram<'a>, CycleError> {
+        let mut beams = vec![vec![(false, false); manifold.n_cols]; manifold.n_rows];
+        let propagation =
+            manifold.simulate_with(EdgePolicy::Lost, |row, col, direction, _| match direction {
+                Direction::Up | Direction::Down => beams[row][col].0 = true,
+                Direction::Left | Direction::Right => beams[row][col].1 = true,
+            })?;
+        Ok(Diagram {
+            manifold,
+            beams,
+            propagation,
+        })
+    }
+
+    /// Draws the manifold as text, with `|` and `-` where a vertical or
+    /// horizontal beam passes and `+` where they cross, `^` where a splitter
+    /// was hit and `x` for splitters no vertical beam reached, followed by the
+    /// number of timelines leaving each column
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for (row, beams) in self.beams.iter().enumerate() {
+            for (col, beam) in beams.iter().enumerate() {
+                text.push(match (self.manifold.cells[row][col], *beam) {
+                    (Cell::Empty, (true, false)) => '|',
+                    (Cell::Empty, (false, true)) => '-',
+                    (Cell::Empty, (true, true)) => '+',
+                    (Cell::Splitter, (false, _)) => 'x',
+                    _ => self.manifold.symbol(row, col),
+                });
+            }
+            text.push('\n');
+        }
+        for (col, count) in self.propagation.exits.iter().enumerate() {
+            if *count > 0 {
+                text.push_str(&format!("Exit {}: {}\n", col, count));
+            }
+        }
+        text
+    }
+
+    /// Draws the manifold as an SVG with each cell a square of the given size.
+    /// Beams are blue lines along their direction, splitters hit by a vertical
+    /// beam red and idle ones grey, and the number of timelines leaving each
+    /// column is written below it.
+    fn svg(&self, scale: usize) -> String {
+        let width = self.manifold.n_cols * scale;
+        let height = (self.manifold.n_rows + 2) * scale;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n",
+            width, height
+        );
+        let half = scale as f64 / 2.0;
+        for (row, beams) in self.beams.iter().enumerate() {
+            for (col, beam) in beams.iter().enumerate() {
+                let (x, y) = ((col * scale) as f64, (row * scale) as f64);
+                let (cx, cy) = (x + half, y + half);
+                let (right, bottom) = (x + scale as f64, y + scale as f64);
+                let shape = match (self.manifold.cells[row][col], *beam) {
+                    (Cell::Empty, (false, false)) => continue,
+                    (Cell::Empty, (vertical, horizontal)) => {
+                        let mut lines = Vec::new();
+                        if vertical {
+                            lines.push(format!(
+                                "<line x1=\"{cx}\" y1=\"{y}\" x2=\"{cx}\" y2=\"{bottom}\" stroke=\"blue\"/>"
+                            ));
+                        }
+                        if horizontal {
+                            lines.push(format!(
+                                "<line x1=\"{x}\" y1=\"{cy}\" x2=\"{right}\" y2=\"{cy}\" stroke=\"blue\"/>"
+                            ));
+                        }
+                        lines.join("\n")
+                    }
+                    (Cell::Start, _) => {
+                        format!("<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{half}\" fill=\"green\"/>")
+                    }
+                    (Cell::Splitter, (hit, _)) => format!(
+                        "<polygon points=\"{cx},{y} {x},{bottom} {right},{bottom}\" fill=\"{}\"/>",
+                        if hit { "red" } else { "grey" }
+                    ),
+                    (Cell::ForwardMirror, _) => format!(
+                        "<line x1=\"{x}\" y1=\"{bottom}\" x2=\"{right}\" y2=\"{y}\" stroke=\"black\"/>"
+                    ),
+                    (Cell::BackMirror, _) => format!(
+                        "<line x1=\"{x}\" y1=\"{y}\" x2=\"{right}\" y2=\"{bottom}\" stroke=\"black\"/>"
+                    ),
+                    (Cell::Absorber, _) => format!(
+                        "<rect x=\"{x}\" y=\"{y}\" width=\"{scale}\" height=\"{scale}\" fill=\"black\"/>"
+                    ),
+                    (Cell::Merger, _) => format!(
+                        "<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{half}\" fill=\"none\" stroke=\"black\"/>"
+                    ),
+                };
+                svg.push_str(&shape);
+                svg.push('\n');
+            }
+        }
+        let y = (self.manifold.n_rows + 1) * scale;
+        for (col, count) in self.propagation.exits.iter().enumerate() {
+            if *count > 0 {
+                svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                    col as f64 * scale as f64 + half,
+                    y,
+                    half,
+                    count
+                ));
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// A single timeline, given by the start column followed by the column the
+/// beam is in after each row of splitters
+#[derive(Debug, PartialEq)]
+struct Timeline {
+    columns: Vec<usize>,
+}
+impl fmt::Display for Timeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let columns: Vec<String> = self.columns.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}", columns.join(","))
+    }
+}
+
+/// The timelines through rows of splitters, ordered lexicographically by their
+/// columns with a beam going left before it goes right. The number of
+/// timelines leading from each cell to the bottom is counted backwards up the
+/// manifold, so any timeline can be found from its index without walking the
+/// ones before it. Only rows of splitters can be listed this way, as mirrors
+/// and mergers would make a timeline more than one column per row.
+struct Timelines<'a> {
+    beamsplitters: &'a [Vec<bool>],
+    starts: Vec<usize>,
+    policy: EdgePolicy,
+    width: usize,
+    ways: Vec<Vec<u64>>,
+}
+impl<'a> Timelines<'a> {
+    fn new(
+        beamsplitters: &'a [Vec<bool>],
+        initial_positions: &HashSet<usize>,
+        policy: EdgePolicy,
+    ) -> Timelines<'a> {
+        let mut starts: Vec<usize> = initial_positions.iter().copied().collect();
+        starts.sort();
+        let width = beamsplitters
+            .first()
+            .map_or(0, |r| r.len())
+            .max(starts.last().map_or(0, |p| p + 1));
+        let mut timelines = Timelines {
+            beamsplitters,
+            starts,
+            policy,
+            width,
+            ways: vec![vec![1; width]],
+        };
+        for row in (0..beamsplitters.len()).rev() {
+            let below = timelines.ways.last().unwrap();
+            let ways = (0..width)
+                .map(|col| {
+                    timelines
+                        .next_columns(row, col)
+                        .iter()
+                        .map(|c| below[*c])
+                        .sum()
+                })
+                .collect();
+            timelines.ways.push(ways);
+        }
+        // Counts were found from the bottom up
+        timelines.ways.reverse();
+        timelines
+    }
+
+    /// Finds the columns a beam in a column moves to after passing a row, in
+    /// order of column so a beam wrapped round the edge keeps the order
+    fn next_columns(&self, row: usize, col: usize) -> Vec<usize> {
+        if self.beamsplitters[row].get(col).copied().unwrap_or(false) {
+            let mut columns: Vec<usize> = [false, true]
+                .into_iter()
+                .filter_map(|right| self.policy.target(col, right, self.width))
+                .collect();
+            columns.sort();
+            columns
+        } else {
+            vec![col]
+        }
+    }
+
+    /// The total number of timelines from every start
+    fn total(&self) -> u64 {
+        self.starts.iter().map(|s| self.ways[0][*s]).sum()
+    }
+
+    /// Finds the timeline at an index, or None if there are not that many
+    fn nth(&self, mut index: u64) -> Option<Timeline> {
+        let mut col = None;
+        for start in &self.starts {
+            let ways = self.ways[0][*start];
+            if index < ways {
+                col = Some(*start);
+                break;
+            }
+            index -= ways;
+        }
+        let mut col = col?;
+        let mut columns = vec![col];
+        for row in 0..self.beamsplitters.len() {
+            for next in self.next_columns(row, col) {
+                let ways = self.ways[row + 1][next];
+                if index < ways {
+                    col = next;
+                    break;
+                }
+                index -= ways;
+            }
+            columns.push(col);
+        }
+        Some(Timeline { columns })
+    }
+
+    /// Lists the first k timelines in order
+    fn first(&self, k: usize) -> Vec<Timeline> {
+        (0..k as u64).map_while(|i| self.nth(i)).collect()
+    }
+
+    /// Picks a timeline uniformly at random, repeatably for the same seed
+    fn sample(&self, seed: u64) -> Option<Timeline> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        // Reject draws from the incomplete block at the top of the range, so
+        // every timeline is equally likely
+        let limit = u64::MAX - u64::MAX % total;
+        let mut state = seed;
+        loop {
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^= z >> 31;
+            if z < limit {
+                return self.nth(z % total);
             }
         }
-        timelines = new_timelines;
     }
-    timelines.values().sum()
+}
+
+/// Generates a square manifold of splitter rows with a single start in the
+/// middle, using a xorshift generator so runs are repeatable. Splitters are
+/// sparse enough that a timeline meets around 30 of them, keeping the
+/// timeline count within a u64. Manifolds smaller than that are filled with
+/// splitters, and an empty manifold is rejected.
+fn random_manifold(size: usize, seed: u64) -> (Vec<Vec<bool>>, HashSet<usize>) {
+    if size == 0 {
+        panic!("The manifold needs at least one cell.")
+    }
+    let mut state = seed.max(1);
+    let threshold = u64::MAX / size as u64 * 30.min(size as u64);
+    let beamsplitters = (0..size)
+        .map(|_| {
+            (0..size)
+                .map(|pos| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    // Keep the edges clear so beams cannot leave the manifold
+                    pos > 0 && pos < size - 1 && state < threshold
+                })
+                .collect()
+        })
+        .collect();
+    (beamsplitters, HashSet::from([size / 2]))
+}
+
+/// Times propagation through a large generated manifold
+fn benchmark(size: usize) {
+    let (beamsplitters, initial_positions) = random_manifold(size, 2025);
+    println!("Manifold of {} x {} cells", size, size);
+
+    let start = Instant::now();
+    let propagation = propagate(&beamsplitters, &initial_positions, EdgePolicy::Lost);
+    println!(
+        "{} splits and {} timelines in {:?}",
+        propagation.splits,
+        propagation.timelines,
+        start.elapsed()
+    );
 }
 
 fn main() {
-    let (beamsplitters, initial_positions) = parse_file("input.txt");
+    let args: Vec<String> = env::args().collect();
 
-    // Part 1
-    let total = count_number_of_splittings(&beamsplitters, &initial_positions);
-    println!("Total splitting = {}", total);
+    match args.get(1).map(String::as_str) {
+        None => {
+            let manifold = parse_file("input.txt");
 
-    // Part 2
-    let total = count_number_of_timelines(&beamsplitters, &initial_positions);
-    println!("Total splitting = {}", total);
+            // Part 1
+            let total = count_number_of_splittings(&manifold, EdgePolicy::Lost);
+            println!("Total splitting = {}", total);
+
+            // Part 2
+            let total = count_number_of_timelines(&manifold, EdgePolicy::Lost);
+            println!("Total splitting = {}", total);
+        }
+        // Simulate any layout of cells, e.g. `simulate mirrors.txt`
+        Some("simulate") => {
+            let manifold = parse_file(args.get(2).map_or("input.txt", String::as_str));
+            match manifold.simulate(EdgePolicy::Lost) {
+                Ok(propagation) => {
+                    println!("Total splitting = {}", propagation.splits);
+                    println!("Total timelines = {}", propagation.timelines);
+                    println!(
+                        "Lost beams = {} ({} timelines)",
+                        propagation.beams_lost, propagation.timelines_lost
+                    );
+                    println!("Absorbed timelines = {}", propagation.timelines_absorbed);
+                }
+                Err(e) => println!("{}", e),
+            }
+        }
+        // Simulate with a different edge policy, e.g. `edge wrap mirrors.txt`
+        Some("edge") => {
+            let policy = args[2].parse().unwrap();
+            let manifold = parse_file(args.get(3).map_or("input.txt", String::as_str));
+            match manifold.simulate(policy) {
+                Ok(propagation) => {
+                    println!("Total splitting = {}", propagation.splits);
+                    println!("Total timelines = {}", propagation.timelines);
+                    println!(
+                        "Lost beams = {} ({} timelines)",
+                        propagation.beams_lost, propagation.timelines_lost
+                    );
+                }
+                Err(e) => println!("{}", e),
+            }
+        }
+        // List the first timelines, e.g. `timelines 5`
+        Some("timelines") => match parse_file("input.txt").beamsplitter_rows() {
+            Ok((beamsplitters, initial_positions)) => {
+                let timelines =
+                    Timelines::new(&beamsplitters, &initial_positions, EdgePolicy::Lost);
+                for timeline in timelines.first(args.get(2).map_or(10, |s| s.parse().unwrap())) {
+                    println!("{}", timeline);
+                }
+            }
+            Err(e) => println!("{}", e),
+        },
+        // Pick random timelines, e.g. `sample 2025 5`
+        Some("sample") => match parse_file("input.txt").beamsplitter_rows() {
+            Ok((beamsplitters, initial_positions)) => {
+                let timelines =
+                    Timelines::new(&beamsplitters, &initial_positions, EdgePolicy::Lost);
+                let seed: u64 = args[2].parse().unwrap();
+                let n: u64 = args.get(3).map_or(1, |s| s.parse().unwrap());
+                for i in 0..n {
+                    println!("{}", timelines.sample(seed.wrapping_add(i)).unwrap());
+                }
+            }
+            Err(e) => println!("{}", e),
+        },
+        // Show how many timelines leave through each column
+        Some("exits") => {
+            let propagation = parse_file("input.txt").simulate(EdgePolicy::Lost).unwrap();
+            for (col, count) in propagation.exits.iter().enumerate() {
+                if *count > 0 {
+                    println!("{:>4}: {}", col, count);
+                }
+            }
+        }
+        // Draw the beams through a manifold, e.g. `diagram mirrors.txt`
+        Some("diagram") => {
+            let manifold = parse_file(args.get(2).map_or("input.txt", String::as_str));
+            print!("{}", Diagram::new(&manifold).unwrap().text());
+        }
+        // Write the beams through a manifold as an SVG, e.g. `svg beams.svg`
+        Some("svg") => {
+            let manifold = parse_file(args.get(3).map_or("input.txt", String::as_str));
+            let svg = Diagram::new(&manifold).unwrap().svg(10);
+            std::fs::write(&args[2], svg).unwrap();
+        }
+        // Time a generated manifold, ideally with `--release`
+        Some("bench") => benchmark(args.get(2).map_or(10_000, |s| s.parse().unwrap())),
+        Some(other) => panic!("Unrecognised mode {}.", other),
+    }
 }
 
 #[cfg(test)]
@@ -108,19 +914,261 @@ mod tests {
 
     #[test]
     fn part_1() {
-        let (beamsplitters, initial_positions) = parse_file("input.txt");
+        let manifold = parse_file("input.txt");
         assert_eq!(
-            count_number_of_splittings(&beamsplitters, &initial_positions),
+            count_number_of_splittings(&manifold, EdgePolicy::Lost),
             1633
         )
     }
 
     #[test]
     fn part_2() {
-        let (beamsplitters, initial_positions) = parse_file("input.txt");
+        let manifold = parse_file("input.txt");
         assert_eq!(
-            count_number_of_timelines(&beamsplitters, &initial_positions),
+            count_number_of_timelines(&manifold, EdgePolicy::Lost),
             34339203133559
         )
     }
+
+    #[test]
+    fn cell_simulation() {
+        // The cell by cell simulation agrees with dense rows on the input
+        let manifold = parse_file("input.txt");
+        let (beamsplitters, initial_positions) = manifold.beamsplitter_rows().unwrap();
+        assert_eq!(
+            manifold.simulate_cells(EdgePolicy::Lost, |_, _, _, _| {}),
+            Ok(propagate(
+                &beamsplitters,
+                &initial_positions,
+                EdgePolicy::Lost
+            ))
+        );
+
+        // Splitters on consecutive rows are all kept
+        let manifold = Manifold::parse("..S..\n..^..\n.^.^.".as_bytes());
+        let propagation = manifold.simulate(EdgePolicy::Lost).unwrap();
+        assert_eq!((propagation.splits, propagation.timelines), (3, 4));
+        assert_eq!(
+            manifold.simulate_cells(EdgePolicy::Lost, |_, _, _, _| {}),
+            Ok(propagation)
+        );
+    }
+
+    #[test]
+    fn mirrors_absorbers_and_mergers() {
+        let manifold = Manifold::parse(".S..\n.\\.\\\n....".as_bytes());
+        let propagation = manifold.simulate(EdgePolicy::Lost).unwrap();
+        assert_eq!((propagation.timelines, propagation.beams_lost), (1, 0));
+
+        let manifold = Manifold::parse("..S..\n..^..\n.#.o.\n.....".as_bytes());
+        let propagation = manifold.simulate(EdgePolicy::Lost).unwrap();
+        assert_eq!((propagation.splits, propagation.timelines), (1, 1));
+        assert_eq!(propagation.timelines_absorbed, 1);
+
+        // Both sides of the split are turned into the same merger
+        let manifold = Manifold::parse("..S..\n..^..\n.\\o/.\n.....".as_bytes());
+        let propagation = manifold.simulate(EdgePolicy::Lost).unwrap();
+        assert_eq!((propagation.splits, propagation.timelines), (1, 1));
+    }
+
+    #[test]
+    fn looping_beams() {
+        let manifold = Manifold::parse(".S...\n.o..\\\n.....\n.\\../\n.....".as_bytes());
+        assert!(manifold.simulate(EdgePolicy::Lost).is_err());
+    }
+
+    #[test]
+    fn dense_propagation() {
+        // Two splitters side by side share the middle column, which then
+        // carries two timelines into the final splitter
+        let beamsplitters = vec![
+            vec![false, false, true, false, false],
+            vec![false, true, false, true, false],
+            vec![false, false, true, false, false],
+        ];
+        let propagation = propagate(&beamsplitters, &HashSet::from([2]), EdgePolicy::Lost);
+        assert_eq!((propagation.splits, propagation.timelines), (4, 6));
+        let propagation = propagate(&[], &HashSet::from([0, 3]), EdgePolicy::Lost);
+        assert_eq!(propagation.timelines, 2);
+    }
+
+    /// A splitter in the first column followed by one in the last column, so
+    /// beams reach both edges
+    fn edge_manifold() -> Vec<Vec<bool>> {
+        vec![vec![true, false, false], vec![false, false, true]]
+    }
+
+    #[test]
+    fn lost_beams() {
+        let propagation = propagate(&edge_manifold(), &HashSet::from([0]), EdgePolicy::Lost);
+        assert_eq!(
+            propagation,
+            Propagation {
+                splits: 1,
+                timelines: 1,
+                beams_lost: 1,
+                timelines_lost: 1,
+                timelines_absorbed: 0,
+                exits: vec![0, 1, 0]
+            }
+        );
+        // Both timelines reach the last column, so lose twice as many
+        let beamsplitters = vec![vec![false, true, false], vec![false, false, true]];
+        let propagation = propagate(&beamsplitters, &HashSet::from([1]), EdgePolicy::Lost);
+        assert_eq!((propagation.timelines, propagation.beams_lost), (2, 1));
+        assert_eq!(propagation.timelines_lost, 1);
+    }
+
+    #[test]
+    fn reflected_beams() {
+        let propagation = propagate(&edge_manifold(), &HashSet::from([0]), EdgePolicy::Reflect);
+        assert_eq!((propagation.splits, propagation.timelines), (1, 2));
+        assert_eq!(propagation.beams_lost, 0);
+        let propagation = propagate(&[vec![true]], &HashSet::from([0]), EdgePolicy::Reflect);
+        assert_eq!(propagation.timelines, 2);
+    }
+
+    #[test]
+    fn wrapped_beams() {
+        let propagation = propagate(&edge_manifold(), &HashSet::from([0]), EdgePolicy::Wrap);
+        assert_eq!((propagation.splits, propagation.timelines), (2, 3));
+        assert_eq!(propagation.beams_lost, 0);
+        assert_eq!("wrap".parse(), Ok(EdgePolicy::Wrap));
+    }
+
+    #[test]
+    fn generated_manifolds() {
+        // Small manifolds have a splitter in every cell away from the edges
+        let (beamsplitters, initial_positions) = random_manifold(5, 2025);
+        assert!(
+            beamsplitters
+                .iter()
+                .all(|r| r == &[false, true, true, true, false])
+        );
+        assert_eq!(initial_positions, HashSet::from([2]));
+        let (beamsplitters, _) = random_manifold(1, 2025);
+        assert_eq!(beamsplitters, vec![vec![false]]);
+    }
+
+    #[test]
+    fn edge_policies_in_cells() {
+        // Cell by cell simulation handles the edges like dense rows
+        let manifold = Manifold::parse("S..\n^..\n..^".as_bytes());
+        let (beamsplitters, initial_positions) = manifold.beamsplitter_rows().unwrap();
+        assert_eq!(beamsplitters, edge_manifold());
+        for policy in [EdgePolicy::Lost, EdgePolicy::Reflect, EdgePolicy::Wrap] {
+            assert_eq!(
+                manifold.simulate_cells(policy, |_, _, _, _| {}),
+                Ok(propagate(&beamsplitters, &initial_positions, policy))
+            );
+        }
+
+        // A mirror turns the beam out through the right side, where it is
+        // either lost, reflected back up through the mirror or wrapped round
+        // onto the mirror from the left
+        let manifold = Manifold::parse(".S.\n.\\.\n...".as_bytes());
+        assert_eq!(manifold.beamsplitter_rows(), Err(UnsupportedLayout));
+        let propagation = manifold.simulate(EdgePolicy::Lost).unwrap();
+        assert_eq!((propagation.timelines, propagation.beams_lost), (0, 1));
+        let propagation = manifold.simulate(EdgePolicy::Reflect).unwrap();
+        assert_eq!((propagation.timelines, propagation.beams_lost), (0, 1));
+        let propagation = manifold.simulate(EdgePolicy::Wrap).unwrap();
+        assert_eq!((propagation.timelines, propagation.beams_lost), (1, 0));
+        assert_eq!(propagation.exits, vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn enumerate_timelines() {
+        let beamsplitters = vec![
+            vec![false, false, true, false, false],
+            vec![false, true, false, true, false],
+            vec![false, false, true, false, false],
+        ];
+        let initial_positions = HashSet::from([2]);
+        let timelines = Timelines::new(&beamsplitters, &initial_positions, EdgePolicy::Lost);
+        let all: Vec<Vec<usize>> = timelines.first(10).into_iter().map(|t| t.columns).collect();
+        assert_eq!(
+            all,
+            vec![
+                vec![2, 1, 0, 0],
+                vec![2, 1, 2, 1],
+                vec![2, 1, 2, 3],
+                vec![2, 3, 2, 1],
+                vec![2, 3, 2, 3],
+                vec![2, 3, 4, 4],
+            ]
+        );
+        assert_eq!(timelines.first(2).len(), 2);
+        assert_eq!(timelines.nth(6), None);
+
+        // Exits match the final columns of every timeline
+        let propagation = propagate(&beamsplitters, &initial_positions, EdgePolicy::Lost);
+        assert_eq!(propagation.exits, vec![1, 2, 0, 2, 1]);
+
+        // Sampling is repeatable and reaches every timeline
+        assert_eq!(timelines.sample(7), timelines.sample(7));
+        let mut seen = vec![0; all.len()];
+        for seed in 0..600 {
+            let timeline = timelines.sample(seed).unwrap();
+            seen[all.iter().position(|t| *t == timeline.columns).unwrap()] += 1;
+        }
+        assert!(seen.iter().all(|n| *n > 50), "{:?}", seen);
+
+        // Beams wrapped round the edge are still listed in column order
+        let beamsplitters = edge_manifold();
+        let timelines = Timelines::new(&beamsplitters, &HashSet::from([0]), EdgePolicy::Wrap);
+        let all: Vec<Vec<usize>> = timelines.first(10).into_iter().map(|t| t.columns).collect();
+        assert_eq!(all, vec![vec![0, 1, 1], vec![0, 2, 0], vec![0, 2, 1]]);
+        assert_eq!(timelines.total(), 3);
+    }
+
+    #[test]
+    fn input_timelines() {
+        let (beamsplitters, initial_positions) =
+            parse_file("input.txt").beamsplitter_rows().unwrap();
+        let timelines = Timelines::new(&beamsplitters, &initial_positions, EdgePolicy::Lost);
+        assert_eq!(timelines.total(), 34339203133559);
+        let last = timelines.nth(timelines.total() - 1).unwrap();
+        assert_eq!(last.columns.len(), beamsplitters.len() + 1);
+        assert_eq!(timelines.nth(timelines.total()), None);
+    }
+
+    #[test]
+    fn diagrams() {
+        let manifold = Manifold::parse("..S.^\n..^..\n.^.^.\n.....".as_bytes());
+        let diagram = Diagram::new(&manifold).unwrap();
+        assert_eq!(
+            diagram.text(),
+            "..S.x\n..^..\n.^.^.\n|.|.|\nExit 0: 1\nExit 2: 2\nExit 4: 1\n"
+        );
+        let svg = diagram.svg(10);
+        assert_eq!(svg.matches("fill=\"red\"").count(), 3);
+        assert_eq!(svg.matches("fill=\"grey\"").count(), 1);
+        assert_eq!(svg.matches("<text").count(), 3);
+
+        // Beams turned sideways are drawn across, and pass through a splitter
+        // without splitting
+        let manifold = Manifold::parse(".S...\n.\\^.#\n.....".as_bytes());
+        let diagram = Diagram::new(&manifold).unwrap();
+        assert_eq!(diagram.text(), ".S...\n.\\x-#\n.....\n");
+        assert_eq!(
+            diagram
+                .svg(10)
+                .matches("y1=\"15\" x2=\"40\" y2=\"15\"")
+                .count(),
+            1
+        );
+        assert_eq!(diagram.propagation.splits, 0);
+
+        // An idle splitter beside a horizontal beam is not drawn as part of it
+        let manifold = Manifold::parse(".S...\n.\\.#^\n.....".as_bytes());
+        let diagram = Diagram::new(&manifold).unwrap();
+        assert_eq!(diagram.text(), ".S...\n.\\-#x\n.....\n");
+
+        // The diagram comes from the same propagation as the counts
+        let manifold = parse_file("input.txt");
+        let diagram = Diagram::new(&manifold).unwrap();
+        let hit = diagram.text().matches('^').count() as u32;
+        assert_eq!(hit, count_number_of_splittings(&manifold, EdgePolicy::Lost));
+    }
 }
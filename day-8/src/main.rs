@@ -1,34 +1,140 @@
 // Solution to day 8 of the Advent of Code challenge
 
-use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+use std::time::Instant;
 
-/// Stores the x, y and z position of a node
+/// Stores the position of a node, with any number of coordinates
+#[derive(Clone)]
 struct Node {
-    x: u64,
-    y: u64,
-    z: u64,
+    coords: Vec<i64>,
 }
 impl Node {
-    fn new(x: u64, y: u64, z: u64) -> Self {
-        Node { x, y, z }
+    fn new(coords: Vec<i64>) -> Self {
+        Node { coords }
     }
-    /// Returns the square of the distance between the current node and another
-    fn distance_with(&self, node: &Node) -> u64 {
-        self.x.abs_diff(node.x).pow(2)
-            + self.y.abs_diff(node.y).pow(2)
-            + self.z.abs_diff(node.z).pow(2)
+}
+impl From<Vec<i64>> for Node {
+    /// Generate a node from a provided coordinate of the form [x, y, z, ...]
+    fn from(value: Vec<i64>) -> Self {
+        if value.is_empty() {
+            panic!("Vector should have at least 1 element.")
+        }
+        Node::new(value)
+    }
+}
+
+/// The cost of wiring between two nodes. A distance is built by finding the
+/// cost of the difference along each axis and folding these together, so the
+/// cost along a single axis is never more than the whole distance. Arithmetic
+/// saturates rather than overflowing.
+trait Metric {
+    /// The cost of a difference in coordinates along an axis
+    fn along_axis(&self, axis: usize, difference: u64) -> u64;
+    /// Combines the costs along two axes
+    fn fold(&self, a: u64, b: u64) -> u64;
+    /// Returns the distance between two nodes
+    fn distance(&self, a: &Node, b: &Node) -> u64 {
+        a.coords
+            .iter()
+            .zip(&b.coords)
+            .enumerate()
+            .map(|(axis, (p, q))| self.along_axis(axis, p.abs_diff(*q)))
+            .fold(0, |total, cost| self.fold(total, cost))
+    }
+    /// Converts a distance into a length of cable
+    fn length(&self, distance: u64) -> f64 {
+        distance as f64
+    }
+}
+
+/// The square of the straight line distance
+struct SquaredEuclidean;
+impl Metric for SquaredEuclidean {
+    fn along_axis(&self, _: usize, difference: u64) -> u64 {
+        difference.saturating_mul(difference)
+    }
+    fn fold(&self, a: u64, b: u64) -> u64 {
+        a.saturating_add(b)
+    }
+    fn length(&self, distance: u64) -> f64 {
+        (distance as f64).sqrt()
+    }
+}
+
+/// The sum of the differences along each axis
+struct Manhattan;
+impl Metric for Manhattan {
+    fn along_axis(&self, _: usize, difference: u64) -> u64 {
+        difference
+    }
+    fn fold(&self, a: u64, b: u64) -> u64 {
+        a.saturating_add(b)
+    }
+}
+
+/// The largest difference along any axis
+struct Chebyshev;
+impl Metric for Chebyshev {
+    fn along_axis(&self, _: usize, difference: u64) -> u64 {
+        difference
+    }
+    fn fold(&self, a: u64, b: u64) -> u64 {
+        a.max(b)
+    }
+}
+
+/// Another metric with the difference along each axis scaled by a weight,
+/// with axes beyond the given weights having a weight of 1
+struct Weighted {
+    metric: Box<dyn Metric>,
+    weights: Vec<u64>,
+}
+impl Metric for Weighted {
+    fn along_axis(&self, axis: usize, difference: u64) -> u64 {
+        let weight = self.weights.get(axis).copied().unwrap_or(1);
+        self.metric
+            .along_axis(axis, difference.saturating_mul(weight))
+    }
+    fn fold(&self, a: u64, b: u64) -> u64 {
+        self.metric.fold(a, b)
+    }
+    fn length(&self, distance: u64) -> f64 {
+        self.metric.length(distance)
     }
 }
-impl From<Vec<u64>> for Node {
-    /// Generate a node from a provided coordinate of the form [x, y, z]
-    fn from(value: Vec<u64>) -> Self {
-        if value.len() != 3 {
-            panic!("Vector should have 3 elements.")
+
+/// Reads a metric of the form `manhattan` or `manhattan:2,1,1`, where the
+/// numbers after the colon weight each axis
+impl FromStr for Box<dyn Metric> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, weights) = match s.split_once(':') {
+            Some((name, weights)) => (name, Some(weights)),
+            None => (s, None),
+        };
+        let metric: Box<dyn Metric> = match name {
+            "euclidean" => Box::new(SquaredEuclidean),
+            "manhattan" => Box::new(Manhattan),
+            "chebyshev" => Box::new(Chebyshev),
+            _ => return Err(format!("Unrecognised metric {}.", name)),
+        };
+        match weights {
+            Some(weights) => Ok(Box::new(Weighted {
+                metric,
+                weights: weights
+                    .split(',')
+                    .map(|w| w.parse().map_err(|_| format!("Unrecognised weight {}.", w)))
+                    .collect::<Result<_, _>>()?,
+            })),
+            None => Ok(metric),
         }
-        Node::new(value[0], value[1], value[2])
     }
 }
 
@@ -38,86 +144,531 @@ fn parse_file(name: &str) -> Vec<Node> {
     let file = File::open(name).unwrap();
     let reader = BufReader::new(file);
 
-    reader
+    let nodes: Vec<Node> = reader
         .lines()
         .map(|c| {
             Node::from(
                 c.unwrap()
                     .split(",")
                     .map(|s| s.parse().unwrap())
-                    .collect::<Vec<u64>>(),
+                    .collect::<Vec<i64>>(),
             )
         })
-        .collect()
+        .collect();
+    if nodes
+        .iter()
+        .any(|n| n.coords.len() != nodes[0].coords.len())
+    {
+        panic!("Every node should have the same number of coordinates.")
+    }
+    nodes
 }
 
-/// Finds all possible nodes and the distances between them, and then sorts from
-/// shortest to largest
-fn find_all_distances_sorted(nodes: &[Node]) -> Vec<((usize, usize), u64)> {
-    let mut distances: Vec<((usize, usize), u64)> = (0..nodes.len())
-        .flat_map(|i| {
-            (i + 1..nodes.len()).map(move |j| ((i, j), nodes[i].distance_with(&nodes[j])))
-        })
-        .collect();
-    distances.sort_by_key(|(_, d)| *d);
-    distances
+/// A range of the k-d tree split around its median for a target node
+struct Split {
+    /// The position of the median within the ordering
+    mid: usize,
+    /// The range on the same side of the median as the target
+    near: (usize, usize),
+    /// The range on the far side of the median from the target
+    far: (usize, usize),
+    /// The distance from the target to the splitting plane
+    plane: u64,
+}
+
+/// A k-d tree over the junction boxes, stored as an ordering of the node
+/// indices. The middle of each range is the median of that range along the
+/// axis for its depth, with smaller coordinates before it and larger after.
+struct KdTree<'a> {
+    nodes: &'a [Node],
+    metric: &'a dyn Metric,
+    dimensions: usize,
+    order: Vec<usize>,
+}
+impl<'a> KdTree<'a> {
+    fn new(nodes: &'a [Node], metric: &'a dyn Metric) -> Self {
+        let dimensions = nodes.first().map_or(1, |n| n.coords.len());
+        let mut order: Vec<usize> = (0..nodes.len()).collect();
+        Self::build(nodes, dimensions, &mut order, 0);
+        KdTree {
+            nodes,
+            metric,
+            dimensions,
+            order,
+        }
+    }
+
+    fn build(nodes: &[Node], dimensions: usize, order: &mut [usize], depth: usize) {
+        if order.len() <= 1 {
+            return;
+        }
+        let mid = order.len() / 2;
+        order.select_nth_unstable_by_key(mid, |i| nodes[*i].coords[depth % dimensions]);
+        let (left, right) = order.split_at_mut(mid);
+        Self::build(nodes, dimensions, left, depth + 1);
+        Self::build(nodes, dimensions, &mut right[1..], depth + 1);
+    }
+
+    /// Splits a range around its median, as seen from the target
+    fn split(&self, target: usize, lo: usize, hi: usize, depth: usize) -> Split {
+        let mid = lo + (hi - lo) / 2;
+        let axis = depth % self.dimensions;
+        let t = self.nodes[target].coords[axis];
+        let s = self.nodes[self.order[mid]].coords[axis];
+        let plane = self.metric.along_axis(axis, t.abs_diff(s));
+        let (near, far) = if t < s {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        Split {
+            mid,
+            near,
+            far,
+            plane,
+        }
+    }
+
+    /// Finds the k nodes nearest to a node, other than itself, sorted by
+    /// distance and then index
+    fn nearest(&self, target: usize, k: usize) -> Vec<(u64, usize)> {
+        let mut best = BinaryHeap::new();
+        self.search_nearest(target, k, (0, self.order.len()), 0, &mut best);
+        best.into_sorted_vec()
+    }
+
+    fn search_nearest(
+        &self,
+        target: usize,
+        k: usize,
+        (lo, hi): (usize, usize),
+        depth: usize,
+        best: &mut BinaryHeap<(u64, usize)>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let split = self.split(target, lo, hi, depth);
+        let node = self.order[split.mid];
+        if node != target {
+            let d = self.metric.distance(&self.nodes[target], &self.nodes[node]);
+            best.push((d, node));
+            if best.len() > k {
+                best.pop();
+            }
+        }
+        self.search_nearest(target, k, split.near, depth + 1, best);
+        // Planes at the same distance as the worst kept node are crossed, so
+        // ties are broken by index exactly
+        if best.len() < k || split.plane <= best.peek().unwrap().0 {
+            self.search_nearest(target, k, split.far, depth + 1, best);
+        }
+    }
+
+    /// Labels the median of every range whose nodes all share a circuit with
+    /// that circuit, returning the label of the given range
+    fn label_circuits(
+        &self,
+        circuit: &[usize],
+        (lo, hi): (usize, usize),
+        labels: &mut [Option<usize>],
+    ) -> Option<usize> {
+        let mid = lo + (hi - lo) / 2;
+        let own = Some(circuit[self.order[mid]]);
+        let left = match lo < mid {
+            true => self.label_circuits(circuit, (lo, mid), labels),
+            false => own,
+        };
+        let right = match mid + 1 < hi {
+            true => self.label_circuits(circuit, (mid + 1, hi), labels),
+            false => own,
+        };
+        labels[mid] = if left == own && right == own {
+            own
+        } else {
+            None
+        };
+        labels[mid]
+    }
+
+    /// Finds the shortest connection from a node to any node in a different
+    /// circuit, if shorter than the best found so far. Connections are ordered
+    /// by distance, then lower index, then higher index.
+    fn search_other_circuit(
+        &self,
+        target: usize,
+        circuit: &[usize],
+        labels: &[Option<usize>],
+        (lo, hi): (usize, usize),
+        depth: usize,
+        best: &mut Option<(u64, usize, usize)>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let split = self.split(target, lo, hi, depth);
+        // Skip ranges lying entirely within the target's own circuit
+        if labels[split.mid] == Some(circuit[target]) {
+            return;
+        }
+        let node = self.order[split.mid];
+        if circuit[node] != circuit[target] {
+            let d = self.metric.distance(&self.nodes[target], &self.nodes[node]);
+            let connection = (d, target.min(node), target.max(node));
+            if best.is_none_or(|b| connection < b) {
+                *best = Some(connection);
+            }
+        }
+        self.search_other_circuit(target, circuit, labels, split.near, depth + 1, best);
+        if best.is_none_or(|b| split.plane <= b.0) {
+            self.search_other_circuit(target, circuit, labels, split.far, depth + 1, best);
+        }
+    }
+}
+
+/// Generates connections between nodes from shortest to longest, ordered by
+/// distance, then lower index, then higher index, without finding every pair.
+/// Each node holds a list of its nearest neighbours from the k-d tree and a
+/// cursor into it, with the next connection from every node kept in a heap.
+/// A node's list is doubled in length once its cursor reaches the end.
+struct ShortestConnections<'a> {
+    tree: KdTree<'a>,
+    neighbours: Vec<Vec<(u64, usize)>>,
+    cursors: Vec<usize>,
+    heap: BinaryHeap<Reverse<(u64, usize, usize, usize)>>,
+}
+impl<'a> ShortestConnections<'a> {
+    fn new(nodes: &'a [Node], metric: &'a dyn Metric) -> Self {
+        let tree = KdTree::new(nodes, metric);
+        let neighbours = (0..nodes.len()).map(|i| tree.nearest(i, 8)).collect();
+        let mut connections = ShortestConnections {
+            tree,
+            neighbours,
+            cursors: vec![0; nodes.len()],
+            heap: BinaryHeap::new(),
+        };
+        for i in 0..nodes.len() {
+            connections.push(i);
+        }
+        connections
+    }
+
+    /// Adds the connection at the node's cursor to the heap, extending its
+    /// list of neighbours if needed
+    fn push(&mut self, node: usize) {
+        let cursor = self.cursors[node];
+        let found = self.neighbours[node].len();
+        if cursor == found && found < self.tree.nodes.len() - 1 {
+            self.neighbours[node] = self.tree.nearest(node, found * 2);
+        }
+        if let Some((d, other)) = self.neighbours[node].get(cursor) {
+            let (low, high) = (node.min(*other), node.max(*other));
+            self.heap.push(Reverse((*d, low, high, node)));
+        }
+    }
+}
+impl Iterator for ShortestConnections<'_> {
+    type Item = ((usize, usize), u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Reverse((d, low, high, node)) = self.heap.pop()?;
+            self.cursors[node] += 1;
+            self.push(node);
+            // Every connection is found from both ends, so only keep the one
+            // found from the lower index
+            if node == low {
+                return Some(((low, high), d));
+            }
+        }
+    }
+}
+
+/// How connections of equal length are ordered. Connections are always
+/// ordered by distance first, and then by the rank of their nodes: the lower
+/// ranked node, then the higher ranked node. Connections are listed with their
+/// lower ranked node first.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum TieBreak {
+    /// Nodes are ranked by their line in the input, as if every connection
+    /// were sorted from a list of all pairs
+    #[default]
+    Index,
+    /// Nodes later in the input are ranked first
+    ReverseIndex,
+    /// Nodes are ranked by their first coordinate, then their second, and so
+    /// on, and then by index, so the order of the input does not matter
+    Coordinates,
+}
+impl TieBreak {
+    /// Reorders the nodes by rank, so searching the reordered nodes in index
+    /// order breaks ties by this policy
+    fn rank(&self, nodes: &[Node]) -> Ranked {
+        let mut ranking: Vec<usize> = (0..nodes.len()).collect();
+        match self {
+            TieBreak::Index => (),
+            TieBreak::ReverseIndex => ranking.reverse(),
+            TieBreak::Coordinates => {
+                ranking.sort_by(|a, b| nodes[*a].coords.cmp(&nodes[*b].coords))
+            }
+        }
+        Ranked {
+            nodes: ranking.iter().map(|i| nodes[*i].clone()).collect(),
+            ranking,
+        }
+    }
+}
+impl FromStr for TieBreak {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "index" => Ok(TieBreak::Index),
+            "reverse-index" => Ok(TieBreak::ReverseIndex),
+            "coordinates" => Ok(TieBreak::Coordinates),
+            _ => Err(format!("Unrecognised tie break {}.", s)),
+        }
+    }
+}
+
+/// Nodes reordered by a tie-break policy, along with the original index of
+/// the node at each rank
+struct Ranked {
+    nodes: Vec<Node>,
+    ranking: Vec<usize>,
+}
+impl Ranked {
+    /// Converts a connection between ranks to one between original indices
+    fn original(&self, (a, b): (usize, usize)) -> (usize, usize) {
+        (self.ranking[a], self.ranking[b])
+    }
 }
 
 /// Generates a vector of the n shortest connections between nodes, sorted from
-/// shortest to largest distance
-fn find_n_shortest_connections(nodes: &[Node], n: usize) -> Vec<(usize, usize)> {
-    find_all_distances_sorted(nodes)
-        .get(0..n)
-        .unwrap()
-        .iter()
-        .map(|(c, _)| *c)
+/// shortest to largest distance with ties broken by the given policy
+fn find_n_shortest_connections(
+    nodes: &[Node],
+    n: usize,
+    tie_break: TieBreak,
+    metric: &dyn Metric,
+) -> Vec<(usize, usize)> {
+    let ranked = tie_break.rank(nodes);
+    ShortestConnections::new(&ranked.nodes, metric)
+        .take(n)
+        .map(|(c, _)| ranked.original(c))
         .collect()
 }
 
-/// Generates a vector of connections between nodes, sorted from shortest to
-/// largest distance
-fn find_all_connections(nodes: &[Node]) -> Vec<(usize, usize)> {
-    find_all_distances_sorted(nodes)
-        .iter()
-        .map(|(c, _)| *c)
+/// Finds the connections forming a minimum spanning tree over all nodes using
+/// Borůvka's algorithm, where every circuit is joined to its nearest other
+/// circuit each round. Returned in the order given by the tie-break policy,
+/// which is the order they would be added from the sorted list of every
+/// connection.
+fn find_spanning_connections(
+    nodes: &[Node],
+    tie_break: TieBreak,
+    metric: &dyn Metric,
+) -> Vec<((usize, usize), u64)> {
+    let ranked = tie_break.rank(nodes);
+    let nodes = &ranked.nodes;
+    let tree = KdTree::new(nodes, metric);
+    let mut circuits = DisjointSet::new(nodes.len());
+    let mut connections = Vec::new();
+    let mut labels = vec![None; nodes.len()];
+    while circuits.n_sets > 1 {
+        let circuit: Vec<usize> = (0..nodes.len()).map(|i| circuits.find(i)).collect();
+        tree.label_circuits(&circuit, (0, nodes.len()), &mut labels);
+        let mut shortest: Vec<Option<(u64, usize, usize)>> = vec![None; nodes.len()];
+        for i in 0..nodes.len() {
+            let best = &mut shortest[circuit[i]];
+            tree.search_other_circuit(i, &circuit, &labels, (0, nodes.len()), 0, best);
+        }
+        for (d, a, b) in shortest.into_iter().flatten() {
+            if circuits.union(a, b) {
+                connections.push(((a, b), d));
+            }
+        }
+    }
+    connections.sort_by_key(|((a, b), d)| (*d, *a, *b));
+    connections
+        .into_iter()
+        .map(|(c, d)| (ranked.original(c), d))
         .collect()
 }
 
-/// Adds a new connection to a set of circuits, either merging existing
-/// circuits, adding a node to an existing circuit or creating a new circuit.
-fn add_connection_to_circuits(circuits: &mut Vec<HashSet<usize>>, connection: &(usize, usize)) {
-    let loc1 = circuits.iter().position(|c| c.contains(&connection.0));
-    let loc2 = circuits.iter().position(|c| c.contains(&connection.1));
-    match (loc1, loc2) {
-        (Some(i), Some(j)) => {
-            let (i, j) = match i.cmp(&j) {
-                Ordering::Less => (i, j),
-                Ordering::Greater => (j, i),
-                Ordering::Equal => return,
-            };
-            let to_add = circuits.remove(j);
-            circuits[i].extend(to_add);
+/// Connections of equal length where the tie-break policy decides which are
+/// chosen, along with the other connections of that length which could have
+/// been chosen under another policy
+#[derive(Debug, PartialEq)]
+struct Tie {
+    distance: u64,
+    chosen: Vec<(usize, usize)>,
+    alternatives: Vec<(usize, usize)>,
+}
+impl fmt::Display for Tie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "connections of length {}, chose {:?} but {:?} could also have been chosen",
+            self.distance, self.chosen, self.alternatives
+        )
+    }
+}
+
+/// Finds whether the longest of the n shortest connections ties with any
+/// connection which is left out, in a way which changes the product of the
+/// largest circuits. Each chosen connection in the tie is swapped in turn for
+/// each alternative, and the tie is only reported if a swap changes the
+/// product.
+fn find_cut_off_tie(
+    nodes: &[Node],
+    n: usize,
+    n_largest: usize,
+    tie_break: TieBreak,
+    metric: &dyn Metric,
+) -> Option<Tie> {
+    let ranked = tie_break.rank(nodes);
+    let mut connections = ShortestConnections::new(&ranked.nodes, metric);
+    let shortest: Vec<((usize, usize), u64)> = connections.by_ref().take(n).collect();
+    let distance = shortest.last()?.1;
+    let alternatives: Vec<(usize, usize)> = connections
+        .take_while(|(_, d)| *d == distance)
+        .map(|(c, _)| ranked.original(c))
+        .collect();
+    if alternatives.is_empty() {
+        return None;
+    }
+    let connections: Vec<(usize, usize)> =
+        shortest.iter().map(|(c, _)| ranked.original(*c)).collect();
+    let answer = find_and_multiply_n_largest_circuits(&connections, nodes.len(), n_largest);
+    // Ties are sorted last among the shortest connections
+    let n_tied = shortest.iter().filter(|(_, d)| *d == distance).count();
+    let tied = connections.len() - n_tied..connections.len();
+    let changes_answer = tied.clone().any(|i| {
+        alternatives.iter().any(|alternative| {
+            let mut swapped = connections.clone();
+            swapped[i] = *alternative;
+            find_and_multiply_n_largest_circuits(&swapped, nodes.len(), n_largest) != answer
+        })
+    });
+    if !changes_answer {
+        return None;
+    }
+    let chosen = connections[tied].to_vec();
+    Some(Tie {
+        distance,
+        chosen,
+        alternatives,
+    })
+}
+
+/// Finds whether any other connection of the same length could be the last
+/// one needed to complete the circuit. These are the connections of that
+/// length joining two different circuits formed by every shorter connection.
+fn find_last_connection_tie(
+    nodes: &[Node],
+    tie_break: TieBreak,
+    metric: &dyn Metric,
+) -> Option<Tie> {
+    let spanning = find_spanning_connections(nodes, tie_break, metric);
+    let (last, distance) = *spanning.last()?;
+    let mut circuits = DisjointSet::new(nodes.len());
+    for ((a, b), _) in spanning.iter().filter(|(_, d)| *d < distance) {
+        circuits.union(*a, *b);
+    }
+    let ranked = tie_break.rank(nodes);
+    let alternatives: Vec<(usize, usize)> = ShortestConnections::new(&ranked.nodes, metric)
+        .skip_while(|(_, d)| *d < distance)
+        .take_while(|(_, d)| *d == distance)
+        .map(|(c, _)| ranked.original(c))
+        .filter(|(a, b)| (*a, *b) != last && circuits.find(*a) != circuits.find(*b))
+        .collect();
+    if alternatives.is_empty() {
+        return None;
+    }
+    Some(Tie {
+        distance,
+        chosen: vec![last],
+        alternatives,
+    })
+}
+
+/// Tracks which junction boxes share a circuit as a forest, where each
+/// circuit is a tree whose root represents it. Finding a root compresses the
+/// path to it, and merging hangs the smaller tree from the larger, so both
+/// operations take close to constant time.
+struct DisjointSet {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+    n_sets: usize,
+}
+impl DisjointSet {
+    /// Creates a set with each of the n nodes in a circuit of its own
+    fn new(n: usize) -> Self {
+        DisjointSet {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+            n_sets: n,
         }
-        (Some(i), None) => {
-            circuits[i].insert(connection.1);
+    }
+
+    /// Finds the root of the circuit containing a node
+    fn find(&mut self, node: usize) -> usize {
+        let mut root = node;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut node = node;
+        while self.parent[node] != root {
+            let next = self.parent[node];
+            self.parent[node] = root;
+            node = next;
         }
-        (None, Some(i)) => {
-            circuits[i].insert(connection.0);
+        root
+    }
+
+    /// Merges the circuits containing two nodes, returning false if they
+    /// were already in the same circuit
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
         }
-        (None, None) => circuits.push(HashSet::from([connection.0, connection.1])),
+        let (large, small) = match self.size[a].cmp(&self.size[b]) {
+            Ordering::Less => (b, a),
+            _ => (a, b),
+        };
+        self.parent[small] = large;
+        self.size[large] += self.size[small];
+        self.n_sets -= 1;
+        true
+    }
+
+    /// The sizes of every circuit
+    fn sizes(&self) -> Vec<usize> {
+        (0..self.parent.len())
+            .filter(|i| self.parent[*i] == *i)
+            .map(|i| self.size[i])
+            .collect()
     }
 }
 
 /// Finds the n-largest circuits created by a set of connections between
-/// junction boxes and multiply the sizes of these circuits together
-fn find_and_multiply_n_largest_circuits(connections: &[(usize, usize)], n: usize) -> usize {
-    let mut circuits = Vec::new();
-    for con in connections {
-        add_connection_to_circuits(&mut circuits, con);
+/// junction boxes and multiply the sizes of these circuits together, or None
+/// if there are fewer than n circuits
+fn find_and_multiply_n_largest_circuits(
+    connections: &[(usize, usize)],
+    n_nodes: usize,
+    n: usize,
+) -> Option<usize> {
+    let mut circuits = DisjointSet::new(n_nodes);
+    for (a, b) in connections {
+        circuits.union(*a, *b);
     }
-    let mut sizes: Vec<usize> = circuits.iter().map(|f| f.len()).collect();
+    let mut sizes = circuits.sizes();
     sizes.sort();
-    sizes[sizes.len() - n..].iter().product()
+    let largest = sizes.len().checked_sub(n)?;
+    Some(sizes[largest..].iter().product())
 }
 
 /// Find the last connection from the vector which is required to connect all
@@ -126,10 +677,9 @@ fn find_last_connection_for_complete_circuit(
     connections: &[(usize, usize)],
     n_nodes: usize,
 ) -> (usize, usize) {
-    let mut circuits = Vec::new();
+    let mut circuits = DisjointSet::new(n_nodes);
     for con in connections.iter() {
-        add_connection_to_circuits(&mut circuits, con);
-        if circuits.len() == 1 && circuits[0].len() == n_nodes {
+        if circuits.union(con.0, con.1) && circuits.n_sets == 1 {
             return *con;
         }
     }
@@ -139,23 +689,280 @@ fn find_last_connection_for_complete_circuit(
 /// Find the last connection required from the vector of connections which is
 /// required to connect all junction boxes. The product of the x coordinates of
 /// the nodes involved in this connection is then found.
-fn get_product_of_last_connection(connections: &[(usize, usize)], nodes: &[Node]) -> u64 {
+fn get_product_of_last_connection(connections: &[(usize, usize)], nodes: &[Node]) -> i64 {
     let last_connection = find_last_connection_for_complete_circuit(connections, nodes.len());
-    nodes[last_connection.0].x * nodes[last_connection.1].x
+    nodes[last_connection.0].coords[0] * nodes[last_connection.1].coords[0]
+}
+
+/// A circuit of junction boxes, with the connections which first joined its
+/// members and the length of cable they need
+struct Circuit {
+    members: Vec<usize>,
+    connections: Vec<((usize, usize), f64)>,
+    cable_length: f64,
+}
+
+/// The circuits formed by a set of connections, largest first. Connections
+/// between boxes already in the same circuit are left out, so each circuit's
+/// connections span it without any loops.
+struct Wiring<'a> {
+    nodes: &'a [Node],
+    circuits: Vec<Circuit>,
+}
+impl<'a> Wiring<'a> {
+    fn new(nodes: &'a [Node], connections: &[(usize, usize)], metric: &dyn Metric) -> Self {
+        let mut sets = DisjointSet::new(nodes.len());
+        let spanning: Vec<(usize, usize)> = connections
+            .iter()
+            .filter(|(a, b)| sets.union(*a, *b))
+            .copied()
+            .collect();
+
+        let mut circuits: Vec<Circuit> = Vec::new();
+        let mut circuit_of_root = vec![None; nodes.len()];
+        for node in 0..nodes.len() {
+            let root = sets.find(node);
+            let i = *circuit_of_root[root].get_or_insert_with(|| {
+                circuits.push(Circuit {
+                    members: Vec::new(),
+                    connections: Vec::new(),
+                    cable_length: 0.0,
+                });
+                circuits.len() - 1
+            });
+            circuits[i].members.push(node);
+        }
+        for (a, b) in spanning {
+            let length = metric.length(metric.distance(&nodes[a], &nodes[b]));
+            let circuit = &mut circuits[circuit_of_root[sets.find(a)].unwrap()];
+            circuit.connections.push(((a, b), length));
+            circuit.cable_length += length;
+        }
+        // Circuits are found in order of their lowest member, so sorting by
+        // size keeps that order for equal sizes
+        circuits.sort_by_key(|c| Reverse(c.members.len()));
+        Wiring { nodes, circuits }
+    }
+
+    /// The length of cable needed for every circuit
+    fn cable_length(&self) -> f64 {
+        self.circuits.iter().map(|c| c.cable_length).sum()
+    }
+
+    /// Writes the circuits as JSON, with one circuit per line
+    fn json(&self) -> String {
+        let circuits: Vec<String> = self
+            .circuits
+            .iter()
+            .map(|circuit| {
+                let members: Vec<String> = circuit
+                    .members
+                    .iter()
+                    .map(|i| format!("{{\"index\":{},\"coords\":{:?}}}", i, self.nodes[*i].coords))
+                    .collect();
+                let connections: Vec<String> = circuit
+                    .connections
+                    .iter()
+                    .map(|((a, b), length)| {
+                        format!("{{\"from\":{},\"to\":{},\"length\":{}}}", a, b, length)
+                    })
+                    .collect();
+                format!(
+                    "{{\"members\":[{}],\"connections\":[{}],\"cable_length\":{}}}",
+                    members.join(","),
+                    connections.join(","),
+                    circuit.cable_length
+                )
+            })
+            .collect();
+        format!(
+            "{{\"cable_length\":{},\"circuits\":[\n{}\n]}}\n",
+            self.cable_length(),
+            circuits.join(",\n")
+        )
+    }
+
+    /// Writes the circuits as a Graphviz graph, drawing each circuit of more
+    /// than one box as a cluster and labelling connections with their length
+    fn dot(&self) -> String {
+        let mut dot = format!(
+            "graph circuits {{\n  label=\"{} circuits, cable length {:.2}\";\n",
+            self.circuits.len(),
+            self.cable_length()
+        );
+        for (i, circuit) in self.circuits.iter().enumerate() {
+            let indent = match circuit.members.len() {
+                1 => "  ",
+                n => {
+                    dot.push_str(&format!(
+                        "  subgraph cluster_{} {{\n    label=\"Circuit {}: {} boxes, cable length {:.2}\";\n",
+                        i, i, n, circuit.cable_length
+                    ));
+                    "    "
+                }
+            };
+            for member in &circuit.members {
+                let coords: Vec<String> = self.nodes[*member]
+                    .coords
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect();
+                dot.push_str(&format!(
+                    "{}{} [label=\"{}: {}\"];\n",
+                    indent,
+                    member,
+                    member,
+                    coords.join(",")
+                ));
+            }
+            for ((a, b), length) in &circuit.connections {
+                dot.push_str(&format!(
+                    "{}{} -- {} [label=\"{:.2}\"];\n",
+                    indent, a, b, length
+                ));
+            }
+            if circuit.members.len() > 1 {
+                dot.push_str("  }\n");
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Generates nodes with coordinates from 0 up to a limit, using a xorshift
+/// generator so runs are repeatable
+fn random_nodes(n: usize, dimensions: usize, limit: u64, seed: u64) -> Vec<Node> {
+    let mut state = seed.max(1);
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % limit) as i64
+    };
+    (0..n)
+        .map(|_| Node::new((0..dimensions).map(|_| next()).collect()))
+        .collect()
+}
+
+/// Times both parts on a large number of generated junction boxes
+fn benchmark(n: usize) {
+    let nodes = random_nodes(n, 3, 100_000, 2025);
+    println!("{} junction boxes", n);
+
+    let start = Instant::now();
+    let shortest_connections =
+        find_n_shortest_connections(&nodes, n, TieBreak::Index, &SquaredEuclidean);
+    let total = find_and_multiply_n_largest_circuits(&shortest_connections, n, 3).unwrap();
+    println!("Part 1 = {} in {:?}", total, start.elapsed());
+
+    let start = Instant::now();
+    let spanning_connections: Vec<(usize, usize)> =
+        find_spanning_connections(&nodes, TieBreak::Index, &SquaredEuclidean)
+            .into_iter()
+            .map(|(c, _)| c)
+            .collect();
+    let total = get_product_of_last_connection(&spanning_connections, &nodes);
+    println!("Part 2 = {} in {:?}", total, start.elapsed());
 }
 
 fn main() {
-    let nodes = parse_file("input.txt");
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        None => {
+            let nodes = parse_file("input.txt");
+
+            // Part 1
+            let shortest_connections =
+                find_n_shortest_connections(&nodes, 1000, TieBreak::Index, &SquaredEuclidean);
+            let total = find_and_multiply_n_largest_circuits(&shortest_connections, nodes.len(), 3)
+                .unwrap();
+            println!("Total = {}", total);
 
-    // Part 1
-    let shortest_connections = find_n_shortest_connections(&nodes, 1000);
-    let total = find_and_multiply_n_largest_circuits(&shortest_connections, 3);
-    println!("Total = {}", total);
+            // Part 2
+            let spanning_connections: Vec<(usize, usize)> =
+                find_spanning_connections(&nodes, TieBreak::Index, &SquaredEuclidean)
+                    .into_iter()
+                    .map(|(c, _)| c)
+                    .collect();
+            let total = get_product_of_last_connection(&spanning_connections, &nodes);
+            println!("Product = {}", total);
+        }
+        // Solve with a tie-break policy and report any ties deciding the
+        // answers, e.g. `ties coordinates`
+        Some("ties") => {
+            let nodes = parse_file("input.txt");
+            let tie_break = args.get(2).map_or(TieBreak::Index, |s| s.parse().unwrap());
+
+            let shortest_connections =
+                find_n_shortest_connections(&nodes, 1000, tie_break, &SquaredEuclidean);
+            let total = find_and_multiply_n_largest_circuits(&shortest_connections, nodes.len(), 3)
+                .unwrap();
+            println!("Total = {}", total);
+            match find_cut_off_tie(&nodes, 1000, 3, tie_break, &SquaredEuclidean) {
+                Some(tie) => println!("Tie at the cut-off changing the total: {}", tie),
+                None => println!("No tie at the cut-off changing the total"),
+            }
+
+            let spanning_connections: Vec<(usize, usize)> =
+                find_spanning_connections(&nodes, tie_break, &SquaredEuclidean)
+                    .into_iter()
+                    .map(|(c, _)| c)
+                    .collect();
+            let total = get_product_of_last_connection(&spanning_connections, &nodes);
+            println!("Product = {}", total);
+            match find_last_connection_tie(&nodes, tie_break, &SquaredEuclidean) {
+                Some(tie) => println!("Tie for the last connection: {}", tie),
+                None => println!("No tie for the last connection"),
+            }
+        }
+        // Solve with another wiring cost, e.g. `metric manhattan:1,1,2`
+        Some("metric") => {
+            let nodes = parse_file("input.txt");
+            let metric: Box<dyn Metric> = args[2].parse().unwrap();
+
+            let shortest_connections =
+                find_n_shortest_connections(&nodes, 1000, TieBreak::Index, metric.as_ref());
+            let total = find_and_multiply_n_largest_circuits(&shortest_connections, nodes.len(), 3)
+                .unwrap();
+            println!("Total = {}", total);
 
-    // Part 2
-    let all_connections = find_all_connections(&nodes);
-    let total = get_product_of_last_connection(&all_connections, &nodes);
-    println!("Product = {}", total);
+            let spanning_connections: Vec<(usize, usize)> =
+                find_spanning_connections(&nodes, TieBreak::Index, metric.as_ref())
+                    .into_iter()
+                    .map(|(c, _)| c)
+                    .collect();
+            let total = get_product_of_last_connection(&spanning_connections, &nodes);
+            println!("Product = {}", total);
+        }
+        // Export the circuits from part 1, or the spanning tree of part 2 with
+        // `all`, e.g. `export dot all`
+        Some("export") => {
+            let nodes = parse_file("input.txt");
+            let connections = match args.get(3).map(String::as_str) {
+                None => {
+                    find_n_shortest_connections(&nodes, 1000, TieBreak::Index, &SquaredEuclidean)
+                }
+                Some("all") => {
+                    find_spanning_connections(&nodes, TieBreak::Index, &SquaredEuclidean)
+                        .into_iter()
+                        .map(|(c, _)| c)
+                        .collect()
+                }
+                Some(other) => panic!("Unrecognised connections {}.", other),
+            };
+            let wiring = Wiring::new(&nodes, &connections, &SquaredEuclidean);
+            match args[2].as_str() {
+                "json" => print!("{}", wiring.json()),
+                "dot" => print!("{}", wiring.dot()),
+                other => panic!("Unrecognised format {}.", other),
+            }
+        }
+        // Time generated junction boxes, ideally with `--release`
+        Some("bench") => benchmark(args.get(2).map_or(100_000, |s| s.parse().unwrap())),
+        Some(other) => panic!("Unrecognised mode {}.", other),
+    }
 }
 
 #[cfg(test)]
@@ -165,20 +972,251 @@ mod tests {
     #[test]
     fn part_1() {
         let nodes = parse_file("input.txt");
-        let shortest_connections = find_n_shortest_connections(&nodes, 1000);
+        let shortest_connections =
+            find_n_shortest_connections(&nodes, 1000, TieBreak::Index, &SquaredEuclidean);
         assert_eq!(
-            find_and_multiply_n_largest_circuits(&shortest_connections, 3),
-            90036
+            find_and_multiply_n_largest_circuits(&shortest_connections, nodes.len(), 3),
+            Some(90036)
         )
     }
 
     #[test]
     fn part_2() {
         let nodes = parse_file("input.txt");
-        let all_connections = find_all_connections(&nodes);
+        let spanning_connections: Vec<(usize, usize)> =
+            find_spanning_connections(&nodes, TieBreak::Index, &SquaredEuclidean)
+                .into_iter()
+                .map(|(c, _)| c)
+                .collect();
         assert_eq!(
-            get_product_of_last_connection(&all_connections, &nodes),
+            get_product_of_last_connection(&spanning_connections, &nodes),
             6083499488
         )
     }
+
+    /// Finds every connection and sorts them, as the original solution did
+    fn find_all_distances_sorted(
+        nodes: &[Node],
+        metric: &dyn Metric,
+    ) -> Vec<((usize, usize), u64)> {
+        let mut distances: Vec<((usize, usize), u64)> = (0..nodes.len())
+            .flat_map(|i| {
+                (i + 1..nodes.len()).map(move |j| ((i, j), metric.distance(&nodes[i], &nodes[j])))
+            })
+            .collect();
+        distances.sort_by_key(|(_, d)| *d);
+        distances
+    }
+
+    #[test]
+    fn shortest_connections() {
+        // A small range of coordinates gives many equal distances and
+        // repeated nodes
+        for (limit, seed, metric) in [
+            (20, 1, "euclidean"),
+            (1000, 2, "euclidean"),
+            (3, 3, "euclidean"),
+            (20, 4, "manhattan"),
+            (20, 5, "chebyshev"),
+            (1000, 6, "manhattan:1,5,2,1"),
+        ] {
+            let metric: Box<dyn Metric> = metric.parse().unwrap();
+            let metric = metric.as_ref();
+            let dimensions = seed as usize % 3 + 2;
+            let nodes = random_nodes(300, dimensions, limit, seed);
+            let all = find_all_distances_sorted(&nodes, metric);
+            let lazy: Vec<_> = ShortestConnections::new(&nodes, metric).collect();
+            assert_eq!(lazy, all);
+
+            // The spanning tree has the same final connection and total
+            // length as adding every connection in order
+            let connections: Vec<(usize, usize)> = all.iter().map(|(c, _)| *c).collect();
+            let spanning = find_spanning_connections(&nodes, TieBreak::Index, metric);
+            assert_eq!(spanning.len(), nodes.len() - 1);
+            assert_eq!(
+                spanning.last().unwrap().0,
+                find_last_connection_for_complete_circuit(&connections, nodes.len())
+            );
+            let mut circuits = DisjointSet::new(nodes.len());
+            let kruskal: u64 = all
+                .iter()
+                .filter(|((a, b), _)| circuits.union(*a, *b))
+                .map(|(_, d)| d)
+                .sum();
+            assert_eq!(spanning.iter().map(|(_, d)| d).sum::<u64>(), kruskal);
+        }
+    }
+
+    #[test]
+    fn disjoint_set() {
+        let mut circuits = DisjointSet::new(6);
+        assert!(circuits.union(0, 1));
+        assert!(circuits.union(2, 3));
+        assert!(circuits.union(1, 3));
+        assert!(!circuits.union(0, 2));
+        assert_eq!(circuits.find(0), circuits.find(3));
+        assert_ne!(circuits.find(0), circuits.find(4));
+        let mut sizes = circuits.sizes();
+        sizes.sort();
+        assert_eq!(sizes, vec![1, 1, 4]);
+        assert_eq!(circuits.n_sets, 3);
+
+        let connections = [(0, 1), (2, 3), (1, 2), (4, 5), (3, 4)];
+        assert_eq!(
+            find_and_multiply_n_largest_circuits(&connections[..4], 6, 2),
+            Some(8)
+        );
+        // Junction boxes without connections are circuits of their own
+        assert_eq!(
+            find_and_multiply_n_largest_circuits(&[(0, 1)], 4, 3),
+            Some(2)
+        );
+        assert_eq!(find_and_multiply_n_largest_circuits(&[(0, 1)], 2, 3), None);
+        assert_eq!(
+            find_last_connection_for_complete_circuit(&connections, 6),
+            (3, 4)
+        );
+    }
+
+    #[test]
+    fn tie_breaks() {
+        // Every side of a square has the same length
+        let nodes: Vec<Node> = [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]]
+            .iter()
+            .map(|c| Node::from(c.to_vec()))
+            .collect();
+        let last = |tie_break| {
+            find_spanning_connections(&nodes, tie_break, &SquaredEuclidean)
+                .last()
+                .unwrap()
+                .0
+        };
+        assert_eq!(
+            find_n_shortest_connections(&nodes, 2, TieBreak::Index, &SquaredEuclidean),
+            vec![(0, 1), (0, 2)]
+        );
+        assert_eq!(last(TieBreak::Index), (1, 3));
+        assert_eq!(
+            find_n_shortest_connections(&nodes, 2, TieBreak::ReverseIndex, &SquaredEuclidean),
+            vec![(3, 2), (3, 1)]
+        );
+        assert_eq!(last(TieBreak::ReverseIndex), (2, 0));
+        assert_eq!(
+            find_n_shortest_connections(
+                &nodes,
+                2,
+                "coordinates".parse().unwrap(),
+                &SquaredEuclidean
+            ),
+            vec![(0, 2), (0, 1)]
+        );
+        assert_eq!(last(TieBreak::Coordinates), (2, 3));
+
+        assert_eq!(
+            find_cut_off_tie(&nodes, 2, 2, TieBreak::Index, &SquaredEuclidean),
+            Some(Tie {
+                distance: 1,
+                chosen: vec![(0, 1), (0, 2)],
+                alternatives: vec![(1, 3), (2, 3)]
+            })
+        );
+        assert_eq!(
+            find_cut_off_tie(&nodes, 4, 2, TieBreak::Index, &SquaredEuclidean),
+            None
+        );
+        // Any three sides join the whole square, so the tie cannot change the
+        // circuits when a far node is added
+        let mut far = nodes.clone();
+        far.push(Node::new(vec![10, 10, 10]));
+        assert_eq!(
+            find_cut_off_tie(&far, 3, 2, TieBreak::Index, &SquaredEuclidean),
+            None
+        );
+        // Any side could be the last one added, so each other side is an
+        // alternative even though the spanning tree also uses two of them
+        let tie = find_last_connection_tie(&nodes, TieBreak::Index, &SquaredEuclidean).unwrap();
+        assert_eq!(
+            tie,
+            Tie {
+                distance: 1,
+                chosen: vec![(1, 3)],
+                alternatives: vec![(0, 1), (0, 2), (2, 3)]
+            }
+        );
+        assert_eq!(
+            tie.to_string(),
+            "connections of length 1, chose [(1, 3)] but [(0, 1), (0, 2), (2, 3)] could also \
+             have been chosen"
+        );
+        for tie_break in [TieBreak::ReverseIndex, TieBreak::Coordinates] {
+            let (a, b) = last(tie_break);
+            assert!(tie.alternatives.contains(&(a.min(b), a.max(b))));
+        }
+
+        // Moving a corner out makes its shorter side the only way to finish
+        let mut nodes = nodes;
+        nodes[3] = Node::new(vec![1, 3, 0]);
+        assert_eq!(
+            find_last_connection_tie(&nodes, TieBreak::Index, &SquaredEuclidean),
+            None
+        );
+    }
+
+    #[test]
+    fn metrics() {
+        let a = Node::new(vec![0, 0, 0]);
+        let b = Node::new(vec![1, -2, 3]);
+        assert_eq!(SquaredEuclidean.distance(&a, &b), 14);
+        assert_eq!(Manhattan.distance(&a, &b), 6);
+        assert_eq!(Chebyshev.distance(&a, &b), 3);
+        let metric: Box<dyn Metric> = "manhattan:2,1".parse().unwrap();
+        assert_eq!(metric.distance(&a, &b), 7);
+        let metric: Box<dyn Metric> = "chebyshev:4,1,1".parse().unwrap();
+        assert_eq!(metric.distance(&a, &b), 4);
+        assert!("taxicab".parse::<Box<dyn Metric>>().is_err());
+        assert!("manhattan:1,x".parse::<Box<dyn Metric>>().is_err());
+
+        // Distances saturate instead of overflowing
+        let far = Node::new(vec![i64::MIN, i64::MAX, 0]);
+        let near = Node::new(vec![i64::MAX, i64::MIN, 0]);
+        assert_eq!(SquaredEuclidean.distance(&far, &near), u64::MAX);
+        assert_eq!(Manhattan.distance(&far, &near), u64::MAX);
+        assert_eq!(Chebyshev.distance(&far, &near), u64::MAX);
+    }
+
+    #[test]
+    fn export_wiring() {
+        let nodes: Vec<Node> = [[0, 0, 0], [3, 4, 0], [10, 0, 0], [20, 20, 20]]
+            .iter()
+            .map(|c| Node::from(c.to_vec()))
+            .collect();
+        // The last connection closes a loop, so is not needed
+        let wiring = Wiring::new(&nodes, &[(0, 1), (0, 2), (1, 2)], &SquaredEuclidean);
+        assert_eq!(wiring.cable_length(), 15.0);
+        assert_eq!(
+            wiring.json(),
+            "{\"cable_length\":15,\"circuits\":[\n\
+             {\"members\":[{\"index\":0,\"coords\":[0, 0, 0]},{\"index\":1,\"coords\":[3, 4, 0]},\
+             {\"index\":2,\"coords\":[10, 0, 0]}],\"connections\":[{\"from\":0,\"to\":1,\"length\":5},\
+             {\"from\":0,\"to\":2,\"length\":10}],\"cable_length\":15},\n\
+             {\"members\":[{\"index\":3,\"coords\":[20, 20, 20]}],\"connections\":[],\"cable_length\":0}\n\
+             ]}\n"
+        );
+        let dot = wiring.dot();
+        assert!(dot.contains("subgraph cluster_0 {"));
+        assert!(dot.contains("    0 -- 2 [label=\"10.00\"];"));
+        assert!(dot.contains("\n  3 [label=\"3: 20,20,20\"];"));
+        assert!(!dot.contains("cluster_1"));
+
+        // The sizes of the circuits agree with part 1
+        let nodes = parse_file("input.txt");
+        let connections =
+            find_n_shortest_connections(&nodes, 1000, TieBreak::Index, &SquaredEuclidean);
+        let wiring = Wiring::new(&nodes, &connections, &SquaredEuclidean);
+        let product: usize = wiring.circuits[..3]
+            .iter()
+            .map(|c| c.members.len())
+            .product();
+        assert_eq!(product, 90036);
+    }
 }